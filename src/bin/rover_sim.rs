@@ -0,0 +1,881 @@
+// ---------------------------------------------------------------------------
+// MOCK ROVER SIMULATOR
+//
+// A stand-in for the rover's AutonomyManager which runs on a developer's
+// machine, so that AutonomyControl can be tested without a rover. It listens
+// for a TCP connection, speaks the `AutoTmTc` protocol, acknowledges the TCs
+// it's sent, answers heartbeats and disconnects, executes locomotion and
+// waypoint TCs, holds time-tagged TCs in a schedule, tracks the rover's mode
+// and sends simulated pose, health and mode telemetry.
+//
+// Usage: rover_sim [--port <port>] [--framing newline|length] [--silent]
+//
+// `--silent` stops the simulator sending heartbeats, which is useful for
+// testing link loss detection.
+// ---------------------------------------------------------------------------
+
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::io::{Read, Write, ErrorKind};
+use std::net::{TcpListener, TcpStream};
+use std::time::{Duration, Instant};
+
+use autonomy_control::tm_tc_interface::{AutoTmTc, TmTcData, ExecTime};
+use autonomy_control::tm_tc_framing::{
+    FramingMode, FrameDecoder, encode_frame, DEFAULT_MAX_FRAME_LEN};
+use autonomy_control::tm_tc_error::TmTcError;
+use autonomy_control::tm_tc_types::RoverMode;
+use autonomy_control::tc_constructor::*;
+use autonomy_control::tm_deconstructor::*;
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+// Port the simulator listens on by default, matching `TmTcIfConfig`
+const DEFAULT_PORT: u16 = 5050;
+
+// Period of the simulation loop
+const CYCLE_PERIOD: Duration = Duration::from_millis(10);
+
+// How the simulated rover follows waypoints
+const TRAVERSE_SPEED_MPS: f64 = 0.3;
+const TRAVERSE_TURN_RATE_RADPS: f64 = 0.4;
+const DEFAULT_WAYPOINT_TOLERANCE_M: f64 = 0.25;
+const HEADING_TOLERANCE_RAD: f64 = 0.05;
+const MAX_DRIVING_HEADING_ERROR_RAD: f64 = 0.3;
+
+// Periods at which each type of telemetry is sent
+const HEARTBEAT_PERIOD: Duration = Duration::from_secs(1);
+const POSE_PERIOD: Duration = Duration::from_millis(100);
+const HEALTH_PERIOD: Duration = Duration::from_secs(1);
+
+// ---------------------------------------------------------------------------
+// CONFIGURATION
+// ---------------------------------------------------------------------------
+
+struct SimConfig {
+    // Port to listen for AutonomyControl on
+    port: u16,
+
+    // Framing mode to use on the stream
+    framing: FramingMode,
+
+    // If false no heartbeats are sent
+    send_heartbeats: bool
+}
+
+impl SimConfig {
+
+    // Build the configuration from the command line arguments
+    fn from_args() -> Result<Self, String> {
+        let mut config = SimConfig {
+            port: DEFAULT_PORT,
+            framing: FramingMode::NewlineDelimited,
+            send_heartbeats: true
+        };
+
+        let mut args = env::args().skip(1);
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--port" => {
+                    config.port = match args.next().map(|p| p.parse()) {
+                        Some(Ok(p)) => p,
+                        _ => return Err(String::from("--port needs a port number"))
+                    }
+                },
+                "--framing" => {
+                    config.framing = match args.next().as_deref() {
+                        Some("newline") => FramingMode::NewlineDelimited,
+                        Some("length") => FramingMode::LengthPrefixed,
+                        _ => return Err(String::from(
+                            "--framing must be either newline or length"))
+                    }
+                },
+                "--silent" => config.send_heartbeats = false,
+                a => return Err(format!("Unknown argument {}", a))
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// SIMULATED ROVER
+// ---------------------------------------------------------------------------
+
+// How the rover moves during one step of the simulation
+struct Motion {
+    // Forward speed, negative in reverse
+    speed_mps: f64,
+
+    // Rate of turn, positive to the left
+    turn_rate_radps: f64,
+
+    // How much of the step the motion lasts for
+    duration_s: f64,
+
+    // True if the activity is complete after this motion
+    finished: bool
+}
+
+impl Motion {
+    // Turn on the spot towards `error_rad`, without overshooting it
+    fn turn_towards(error_rad: f64, dt: f64) -> Self {
+        Motion {
+            speed_mps: 0.0,
+            turn_rate_radps: TRAVERSE_TURN_RATE_RADPS * error_rad.signum(),
+            duration_s: dt.min(error_rad.abs() / TRAVERSE_TURN_RATE_RADPS),
+            finished: false
+        }
+    }
+}
+
+// A locomotion TC being executed by the rover
+struct Manoeuvre {
+    // Forward speed during the manoeuvre, negative in reverse
+    speed_mps: f64,
+
+    // Rate of turn during the manoeuvre, positive to the left
+    turn_rate_radps: f64,
+
+    // Time left until the manoeuvre is complete
+    remaining_s: f64
+}
+
+impl Manoeuvre {
+    fn drive_straight(tc: &TcDriveStraight) -> Self {
+        Manoeuvre {
+            speed_mps: tc.speed_mps * tc.distance_m.signum(),
+            turn_rate_radps: 0.0,
+            remaining_s: tc.distance_m.abs() / tc.speed_mps
+        }
+    }
+
+    fn drive_arc(tc: &TcDriveArc) -> Self {
+        let speed_mps = tc.speed_mps * tc.distance_m.signum();
+
+        Manoeuvre {
+            speed_mps,
+            turn_rate_radps: speed_mps * tc.curvature_pm,
+            remaining_s: tc.distance_m.abs() / tc.speed_mps
+        }
+    }
+
+    fn point_turn(tc: &TcPointTurn) -> Self {
+        Manoeuvre {
+            speed_mps: 0.0,
+            turn_rate_radps: tc.rate_radps * tc.angle_rad.signum(),
+            remaining_s: tc.angle_rad.abs() / tc.rate_radps
+        }
+    }
+
+    fn next_motion(&mut self, dt: f64) -> Motion {
+        // Don't overshoot the end of the manoeuvre
+        let duration_s = dt.min(self.remaining_s);
+        self.remaining_s -= duration_s;
+
+        Motion {
+            speed_mps: self.speed_mps,
+            turn_rate_radps: self.turn_rate_radps,
+            duration_s,
+            finished: self.remaining_s <= 0.0
+        }
+    }
+}
+
+// A waypoint traverse being executed by the rover
+struct Traverse {
+    // Waypoints left to reach, the first is the one being driven to
+    waypoints: VecDeque<Waypoint>,
+
+    // True if the traverse has been paused
+    paused: bool
+}
+
+impl Traverse {
+    fn new(tc: TcNavigateWaypoints) -> Self {
+        Traverse {
+            waypoints: tc.waypoints.into_iter().collect(),
+            paused: false
+        }
+    }
+
+    // Drive towards the next waypoint, turning on the spot if it's too far
+    // off to one side, and then turn to face the waypoint's heading if it
+    // has one.
+    fn next_motion(
+        &mut self, 
+        position_m: [f64; 3], 
+        heading_rad: f64, 
+        dt: f64) -> Motion {
+
+        let mut motion = Motion {
+            speed_mps: 0.0,
+            turn_rate_radps: 0.0,
+            duration_s: dt,
+            finished: false
+        };
+
+        if self.paused {
+            return motion
+        }
+
+        let wp = match self.waypoints.front() {
+            Some(w) => w,
+            None => {
+                motion.finished = true;
+                return motion
+            }
+        };
+
+        let dx = wp.x_m - position_m[0];
+        let dy = wp.y_m - position_m[1];
+        let distance_m = dx.hypot(dy);
+        let tolerance_m = wp.tolerance_m.unwrap_or(DEFAULT_WAYPOINT_TOLERANCE_M);
+
+        if distance_m <= tolerance_m {
+            if let Some(h) = wp.heading_rad {
+                let error_rad = wrap_angle(h - heading_rad);
+                if error_rad.abs() > HEADING_TOLERANCE_RAD {
+                    return Motion::turn_towards(error_rad, dt)
+                }
+            }
+
+            self.waypoints.pop_front();
+            motion.finished = self.waypoints.is_empty();
+            return motion
+        }
+
+        let error_rad = wrap_angle(dy.atan2(dx) - heading_rad);
+        if error_rad.abs() > MAX_DRIVING_HEADING_ERROR_RAD {
+            return Motion::turn_towards(error_rad, dt)
+        }
+
+        // Steer towards the waypoint while driving
+        motion.speed_mps = TRAVERSE_SPEED_MPS;
+        motion.turn_rate_radps = (2.0 * error_rad)
+            .clamp(-TRAVERSE_TURN_RATE_RADPS, TRAVERSE_TURN_RATE_RADPS);
+        motion.duration_s = dt.min(distance_m / TRAVERSE_SPEED_MPS);
+        motion
+    }
+}
+
+// Something the rover is busy doing
+enum Activity {
+    Manoeuvre(Manoeuvre),
+    Traverse(Traverse)
+}
+
+struct SimRover {
+    // Position of the rover in metres
+    position_m: [f64; 3],
+
+    // Heading of the rover about the z axis
+    heading_rad: f64,
+
+    // Speed of the rover in the last step
+    speed_mps: f64,
+
+    // The rover's operating mode
+    mode: RoverMode,
+
+    // What the rover is currently doing, if anything, along with the frame
+    // counter of the TC which started it, used to acknowledge the TC when
+    // it's finished
+    activity: Option<(Activity, Option<u32>)>,
+
+    // Remaining battery charge as a percentage
+    battery_charge_pct: f64
+}
+
+impl SimRover {
+
+    // Create a new rover, stationary at the origin in Standby mode
+    fn new() -> Self {
+        SimRover {
+            position_m: [0.0, 0.0, 0.0],
+            heading_rad: 0.0,
+            speed_mps: 0.0,
+            mode: RoverMode::Standby,
+            activity: None,
+            battery_charge_pct: 100.0
+        }
+    }
+
+    // Start a new activity, returning the frame counter of the TC which
+    // started the one it replaces, if the rover was already busy.
+    fn start(&mut self, activity: Activity, frame_counter: Option<u32>) 
+        -> Option<Option<u32>> {
+
+        self.activity.replace((activity, frame_counter)).map(|(_, f)| f)
+    }
+
+    // Stop whatever the rover is doing, returning the frame counter of the
+    // TC which started it.
+    fn stop(&mut self) -> Option<Option<u32>> {
+        self.activity.take().map(|(_, f)| f)
+    }
+
+    // Get the current traverse, if the rover is on one
+    fn traverse(&mut self) -> Option<&mut Traverse> {
+        match self.activity.as_mut() {
+            Some((Activity::Traverse(t), _)) => Some(t),
+            _ => None
+        }
+    }
+
+    // Move the simulation on by `dt` seconds. Returns the frame counter of
+    // the TC which started the current activity if it finished during this
+    // step.
+    fn step(&mut self, dt: f64) -> Option<Option<u32>> {
+        let motion = match self.activity.as_mut() {
+            Some((Activity::Manoeuvre(m), _)) => m.next_motion(dt),
+            Some((Activity::Traverse(t), _)) => t.next_motion(
+                self.position_m, self.heading_rad, dt),
+            None => Motion {
+                speed_mps: 0.0,
+                turn_rate_radps: 0.0,
+                duration_s: dt,
+                finished: false
+            }
+        };
+
+        self.speed_mps = motion.speed_mps;
+        self.heading_rad = wrap_angle(
+            self.heading_rad + motion.turn_rate_radps * motion.duration_s);
+        self.position_m[0] += 
+            motion.speed_mps * self.heading_rad.cos() * motion.duration_s;
+        self.position_m[1] += 
+            motion.speed_mps * self.heading_rad.sin() * motion.duration_s;
+
+        // Drain the battery faster when moving
+        let drain_pct_per_s = 0.001 + 0.01 * self.speed_mps.abs();
+        self.battery_charge_pct =
+            (self.battery_charge_pct - drain_pct_per_s * dt).max(0.0);
+
+        match motion.finished {
+            true => self.stop(),
+            false => None
+        }
+    }
+
+    fn pose(&self) -> TmRoverPose {
+        let half_heading = self.heading_rad * 0.5;
+
+        TmRoverPose {
+            position_m: self.position_m,
+            attitude_q: [0.0, 0.0, half_heading.sin(), half_heading.cos()],
+            timestamp_utc: Utc::now()
+        }
+    }
+
+    fn health(&self) -> TmRoverHealth {
+        TmRoverHealth {
+            battery_voltage_v: 22.0 + 3.2 * self.battery_charge_pct / 100.0,
+            battery_charge_pct: self.battery_charge_pct,
+            cpu_temp_c: 45.0 + 10.0 * self.speed_mps.abs()
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// SESSION
+// ---------------------------------------------------------------------------
+
+// A connection from AutonomyControl. Unlike on the real rover the schedule
+// is lost if the client disconnects.
+struct SimSession {
+    stream: TcpStream,
+    framing: FramingMode,
+    decoder: FrameDecoder,
+    tm_frame_counter: u32,
+
+    // Time-tagged TCs waiting to be executed
+    schedule: Vec<AutoTmTc>,
+
+    // When each TC finished, and whether it completed successfully, used to
+    // work out when TCs scheduled after them are due
+    finished_tcs: HashMap<u32, (DateTime<Utc>, bool)>
+}
+
+impl SimSession {
+
+    fn new(stream: TcpStream, framing: FramingMode) -> Result<Self, TmTcError> {
+        stream.set_read_timeout(Some(CYCLE_PERIOD))?;
+        stream.set_nodelay(true)?;
+
+        Ok(SimSession {
+            stream,
+            framing,
+            decoder: FrameDecoder::new(framing, DEFAULT_MAX_FRAME_LEN),
+            tm_frame_counter: 0,
+            schedule: vec![],
+            finished_tcs: HashMap::new()
+        })
+    }
+
+    // Send a piece of telemetry to AutonomyControl
+    fn send_tm<T>(&mut self, data: &T) -> Result<(), TmTcError> where
+        T: TmTcData {
+
+        let mut tm = AutoTmTc::from_data(data)?;
+        tm.stamp(self.tm_frame_counter, Utc::now());
+        self.tm_frame_counter = self.tm_frame_counter.wrapping_add(1);
+
+        let frame = encode_frame(self.framing, tm.to_json()?.as_bytes())?;
+        self.stream.write_all(&frame)?;
+
+        Ok(())
+    }
+
+    // Acknowledge the TC with the given frame counter. TCs without a frame
+    // counter can't be acknowledged.
+    fn ack(&mut self, frame_counter: Option<u32>, status: TcAckStatus) 
+        -> Result<(), TmTcError> {
+
+        let frame_counter = match frame_counter {
+            Some(f) => f,
+            None => return Ok(())
+        };
+
+        match status {
+            TcAckStatus::Completed => {
+                self.finished_tcs.insert(frame_counter, (Utc::now(), true));
+            },
+            TcAckStatus::Rejected { .. } | TcAckStatus::Failed { .. } => {
+                self.finished_tcs.insert(frame_counter, (Utc::now(), false));
+            },
+            TcAckStatus::Accepted => ()
+        }
+
+        self.send_tm(&TmTcAck {
+            frame_counter,
+            status
+        })
+    }
+
+    // Work out when a scheduled TC is due. Returns None if this isn't known
+    // yet because the TC it's scheduled after hasn't finished, or an error if
+    // that TC didn't complete and this one will never be executed.
+    fn due_time(&self, tc: &AutoTmTc) -> Option<Result<DateTime<Utc>, String>> {
+        match tc.exec_time() {
+            None => Some(Ok(Utc::now())),
+            Some(ExecTime::Absolute { time_utc }) => Some(Ok(*time_utc)),
+            Some(ExecTime::AfterTc { frame_counter, delay_s }) => {
+                match self.finished_tcs.get(frame_counter) {
+                    Some((t, true)) => Some(Ok(*t + chrono::Duration::milliseconds(
+                        (delay_s * 1000.0) as i64))),
+                    Some((_, false)) => Some(Err(format!(
+                        "TC {} it was scheduled after did not complete", 
+                        frame_counter))),
+                    None => None
+                }
+            }
+        }
+    }
+
+    // Remove the TCs which are due for execution from the schedule, failing
+    // any which can never be executed.
+    fn take_due_tcs(&mut self) -> Result<Vec<AutoTmTc>, TmTcError> {
+        let now = Utc::now();
+        let mut due = vec![];
+        let mut never = vec![];
+
+        for tc in self.schedule.drain(..).collect::<Vec<_>>() {
+            match self.due_time(&tc) {
+                Some(Ok(t)) if t <= now => due.push(tc),
+                Some(Err(reason)) => never.push((tc.frame_counter(), reason)),
+                _ => self.schedule.push(tc)
+            }
+        }
+
+        for (frame_counter, reason) in never {
+            self.ack(frame_counter, TcAckStatus::Failed { reason })?;
+        }
+
+        for tc in due.iter_mut() {
+            tc.set_exec_time(None);
+        }
+
+        Ok(due)
+    }
+
+    // Send the contents of the schedule, in the order the TCs are due. TCs
+    // which aren't due until an earlier TC finishes go at the end.
+    fn send_schedule(&mut self) -> Result<(), TmTcError> {
+        let mut entries: Vec<_> = self.schedule.iter()
+            .filter_map(|tc| Some((
+                self.due_time(tc).and_then(|d| d.ok()),
+                ScheduleEntry {
+                    frame_counter: tc.frame_counter()?,
+                    data_type_id: String::from(tc.data_type_id()),
+                    exec_time: tc.exec_time()?.clone()
+                })))
+            .collect();
+
+        entries.sort_by_key(|(due, _)| (due.is_none(), *due));
+
+        self.send_tm(&TmSchedule {
+            entries: entries.into_iter().map(|(_, e)| e).collect()
+        })
+    }
+
+    // Read any TCs which have arrived. Waits for up to the cycle period if
+    // there's nothing available.
+    fn receive_tcs(&mut self) -> Result<Vec<AutoTmTc>, TmTcError> {
+        let mut read_buf = [0u8; 4096];
+
+        match self.stream.read(&mut read_buf) {
+            Ok(0) => return Err(TmTcError::Io(std::io::Error::new(
+                ErrorKind::ConnectionAborted, "Connection closed by client"))),
+            Ok(n) => self.decoder.push(&read_buf[..n]),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock
+                || e.kind() == ErrorKind::TimedOut => (),
+            Err(e) => return Err(TmTcError::Io(e))
+        }
+
+        let mut tcs = vec![];
+
+        while let Some(frame) = self.decoder.next_frame() {
+            let tc = frame
+                .map_err(TmTcError::from)
+                .and_then(|f| String::from_utf8(f)
+                    .map_err(|e| TmTcError::Protocol(format!("{}", e))))
+                .and_then(|s| AutoTmTc::from_json(&s));
+
+            match tc {
+                Ok(tc) => tcs.push(tc),
+                Err(e) => eprintln!("Discarding TC: {}", e)
+            }
+        }
+
+        Ok(tcs)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TC HANDLING
+// ---------------------------------------------------------------------------
+
+// Parse a TC's data and check it's within range and allowed in the rover's
+// current mode
+fn parse_valid<T>(tc: &AutoTmTc, mode: RoverMode) -> Result<T, TmTcError> where
+    T: TmTcData {
+
+    let data = tc.parse_data::<T>()?;
+    data.validate()?;
+    data.check_mode(mode)?;
+    Ok(data)
+}
+
+// Act on a single TC. Returns false if the session should end.
+fn handle_tc(
+    session: &mut SimSession,
+    rover: &mut SimRover,
+    tc: &AutoTmTc) -> Result<bool, TmTcError> {
+
+    // Heartbeats are too frequent to be worth printing or acknowledging
+    if tc.is::<TcHeartbeat>() {
+        return Ok(true)
+    }
+
+    println!("TC {:?}: {}", tc.frame_counter(), tc.data_type_id());
+
+    let frame_counter = tc.frame_counter();
+
+    // Time-tagged TCs are held until they're due, and then come back through
+    // here without an execution time
+    if tc.exec_time().is_some() {
+        session.ack(frame_counter, TcAckStatus::Accepted)?;
+        session.schedule.push(tc.clone());
+        return Ok(true)
+    }
+
+    if tc.is::<TcScheduleList>() {
+        session.ack(frame_counter, TcAckStatus::Accepted)?;
+        session.send_schedule()?;
+        session.ack(frame_counter, TcAckStatus::Completed)?;
+        return Ok(true)
+    }
+
+    if tc.is::<TcScheduleDelete>() || tc.is::<TcScheduleClear>() {
+        let removed: Vec<_> = if tc.is::<TcScheduleClear>() {
+            session.schedule.drain(..).collect()
+        }
+        else {
+            let index = tc.parse_data::<TcScheduleDelete>().and_then(|d| {
+                session.schedule.iter()
+                    .position(|s| s.frame_counter() == Some(d.frame_counter))
+                    .ok_or_else(|| TmTcError::InvalidParameter(format!(
+                        "No TC with frame counter {} in the schedule",
+                        d.frame_counter)))
+            });
+
+            match index {
+                Ok(i) => vec![session.schedule.remove(i)],
+                Err(e) => {
+                    session.ack(frame_counter, TcAckStatus::Rejected {
+                        reason: format!("{}", e)
+                    })?;
+                    return Ok(true)
+                }
+            }
+        };
+
+        session.ack(frame_counter, TcAckStatus::Accepted)?;
+        for old in removed {
+            session.ack(old.frame_counter(), TcAckStatus::Failed {
+                reason: String::from("Removed from the schedule")
+            })?;
+        }
+        session.ack(frame_counter, TcAckStatus::Completed)?;
+        return Ok(true)
+    }
+
+    if tc.is::<TcDisconnect>() {
+        session.ack(frame_counter, TcAckStatus::Accepted)?;
+        session.send_tm(&TmDisconnectAck {})?;
+        session.ack(frame_counter, TcAckStatus::Completed)?;
+        return Ok(false)
+    }
+
+    // Stop the rover where it is, failing whatever it was doing
+    if tc.is::<TcAbort>() {
+        session.ack(frame_counter, TcAckStatus::Accepted)?;
+
+        if let Some(old) = rover.stop() {
+            session.ack(old, TcAckStatus::Failed {
+                reason: String::from("Aborted")
+            })?;
+        }
+
+        session.ack(frame_counter, TcAckStatus::Completed)?;
+        return Ok(true)
+    }
+
+    // Changing mode stops whatever the rover was doing
+    if tc.is::<TcSetMode>() {
+        let new_mode = match parse_valid::<TcSetMode>(tc, rover.mode) {
+            Ok(m) => m.mode,
+            Err(e) => {
+                session.ack(frame_counter, TcAckStatus::Rejected {
+                    reason: format!("{}", e)
+                })?;
+                return Ok(true)
+            }
+        };
+
+        session.ack(frame_counter, TcAckStatus::Accepted)?;
+
+        // Asking for the mode the rover's already in changes nothing
+        if new_mode == rover.mode {
+            session.ack(frame_counter, TcAckStatus::Completed)?;
+            return Ok(true)
+        }
+
+        if let Some(old) = rover.stop() {
+            session.ack(old, TcAckStatus::Failed {
+                reason: format!("Rover changed to {} mode", new_mode)
+            })?;
+        }
+
+        println!("Rover mode {} -> {}", rover.mode, new_mode);
+        rover.mode = new_mode;
+        session.send_tm(&TmRoverMode { mode: rover.mode })?;
+
+        session.ack(frame_counter, TcAckStatus::Completed)?;
+        return Ok(true)
+    }
+
+    // Traverse control, which only makes sense if there's a traverse going
+    let control = if tc.is::<TcTraversePause>() {
+        Some(parse_valid::<TcTraversePause>(tc, rover.mode).map(|_| ()))
+    }
+    else if tc.is::<TcTraverseResume>() {
+        Some(parse_valid::<TcTraverseResume>(tc, rover.mode).map(|_| ()))
+    }
+    else if tc.is::<TcTraverseCancel>() {
+        Some(parse_valid::<TcTraverseCancel>(tc, rover.mode).map(|_| ()))
+    }
+    else {
+        None
+    };
+
+    if let Some(checked) = control {
+        let rejection = match checked {
+            Err(e) => Some(format!("{}", e)),
+            Ok(_) if rover.traverse().is_none() => {
+                Some(String::from("No traverse in progress"))
+            },
+            Ok(_) => None
+        };
+
+        if let Some(reason) = rejection {
+            session.ack(frame_counter, TcAckStatus::Rejected { reason })?;
+            return Ok(true)
+        }
+
+        session.ack(frame_counter, TcAckStatus::Accepted)?;
+
+        if tc.is::<TcTraverseCancel>() {
+            if let Some(old) = rover.stop() {
+                session.ack(old, TcAckStatus::Failed {
+                    reason: String::from("Cancelled")
+                })?;
+            }
+        }
+        else if let Some(t) = rover.traverse() {
+            t.paused = tc.is::<TcTraversePause>();
+        }
+
+        session.ack(frame_counter, TcAckStatus::Completed)?;
+        return Ok(true)
+    }
+
+    let mode = rover.mode;
+    let activity = if tc.is::<TcDriveStraight>() {
+        parse_valid(tc, mode).map(|d| Activity::Manoeuvre(
+            Manoeuvre::drive_straight(&d)))
+    }
+    else if tc.is::<TcDriveArc>() {
+        parse_valid(tc, mode).map(|d| Activity::Manoeuvre(
+            Manoeuvre::drive_arc(&d)))
+    }
+    else if tc.is::<TcPointTurn>() {
+        parse_valid(tc, mode).map(|d| Activity::Manoeuvre(
+            Manoeuvre::point_turn(&d)))
+    }
+    else if tc.is::<TcNavigateWaypoints>() {
+        parse_valid(tc, mode).map(|d| Activity::Traverse(Traverse::new(d)))
+    }
+    else {
+        Err(TmTcError::UnknownTypeId(String::from(tc.data_type_id())))
+    };
+
+    match activity {
+        Ok(a) => {
+            session.ack(frame_counter, TcAckStatus::Accepted)?;
+
+            if let Some(old) = rover.start(a, frame_counter) {
+                session.ack(old, TcAckStatus::Failed {
+                    reason: String::from("Superseded by a later TC")
+                })?;
+            }
+        },
+        Err(e) => session.ack(frame_counter, TcAckStatus::Rejected {
+            reason: format!("{}", e)
+        })?
+    }
+
+    Ok(true)
+}
+
+// Run a session with a connected client until it disconnects
+fn run_session(
+    config: &SimConfig,
+    rover: &mut SimRover,
+    stream: TcpStream) -> Result<(), TmTcError> {
+
+    let mut session = SimSession::new(stream, config.framing)?;
+
+    let mut last_step = Instant::now();
+    let mut last_heartbeat: Option<Instant> = None;
+    let mut last_pose: Option<Instant> = None;
+    let mut last_health: Option<Instant> = None;
+
+    // Returns true if the given period has passed since `last`, and resets
+    // `last` if so.
+    fn due(last: &mut Option<Instant>, period: Duration) -> bool {
+        match last {
+            Some(t) if t.elapsed() < period => false,
+            _ => {
+                *last = Some(Instant::now());
+                true
+            }
+        }
+    }
+
+    loop {
+        let mut tcs = session.receive_tcs()?;
+        tcs.extend(session.take_due_tcs()?);
+
+        for tc in tcs {
+            if !handle_tc(&mut session, rover, &tc)? {
+                println!("Client disconnected");
+                return Ok(())
+            }
+        }
+
+        if let Some(f) = rover.step(last_step.elapsed().as_secs_f64()) {
+            session.ack(f, TcAckStatus::Completed)?;
+        }
+        last_step = Instant::now();
+
+        if config.send_heartbeats && due(&mut last_heartbeat, HEARTBEAT_PERIOD) {
+            session.send_tm(&TmHeartbeat { current_time_utc: Utc::now() })?;
+        }
+
+        if due(&mut last_pose, POSE_PERIOD) {
+            session.send_tm(&rover.pose())?;
+        }
+
+        if due(&mut last_health, HEALTH_PERIOD) {
+            session.send_tm(&rover.health())?;
+            session.send_tm(&TmRoverMode { mode: rover.mode })?;
+        }
+    }
+}
+
+// Wrap an angle into the range -pi to pi
+fn wrap_angle(angle_rad: f64) -> f64 {
+    use std::f64::consts::PI;
+    (angle_rad + PI).rem_euclid(2.0 * PI) - PI
+}
+
+fn main() {
+    let config = match SimConfig::from_args() {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!(
+                "Usage: rover_sim [--port <port>] [--framing newline|length] [--silent]");
+            std::process::exit(1);
+        }
+    };
+
+    let listener = match TcpListener::bind(("0.0.0.0", config.port)) {
+        Ok(l) => l,
+        Err(e) => {
+            eprintln!("Cannot listen on port {}: {}", config.port, e);
+            std::process::exit(1);
+        }
+    };
+
+    println!("Rover simulator listening on port {}", config.port);
+
+    // The rover carries on from where it was between sessions
+    let mut rover = SimRover::new();
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("Failed to accept connection: {}", e);
+                continue
+            }
+        };
+
+        match stream.peer_addr() {
+            Ok(a) => println!("Client connected from {}", a),
+            Err(_) => println!("Client connected")
+        }
+
+        if let Err(e) = run_session(&config, &mut rover, stream) {
+            eprintln!("Session ended: {}", e);
+        }
+    }
+}
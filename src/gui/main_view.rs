@@ -1,6 +1,8 @@
 use orbtk::{prelude::*, theme::DEFAULT_THEME_CSS};
-use crate::virtspace::VirtSpacePipeline;
-use crate::gui::state::GuiState;
+use crate::virtspace::{VirtSpacePipeline, camera::CameraMode};
+use crate::gui::state::{
+    GuiState, Action, MAX_TC_PARAMS, HISTORY_ROWS, STATUS_ROWS, 
+    PANEL_WIDTH};
 
 // ---------------------------------------------------------------------------
 // THEME IMPORTS
@@ -26,23 +28,319 @@ const DEFAULT_WINDOW_WIDTH: f64 = 1600.0;
 const DEFAULT_WINDOW_HEIGHT: f64 = 900.0;
 
 widget!(
-    MainView<GuiState> {
+    MainView<GuiState>: KeyDownHandler {
         current_time_text: String16,
+        link_text: String16,
+        mode_text: String16,
+        pose_text: String16,
+        health_text: String16,
+        tc_type_text: String16,
+        send_text: String16,
+        sequence_path: String16,
+        run_text: String16,
+
+        // Labels, values and visibility of the TC parameter fields, one for
+        // each of the first `MAX_TC_PARAMS` parameters of the selected type
+        param_label_0: String16,
+        param_label_1: String16,
+        param_label_2: String16,
+        param_label_3: String16,
+        param_value_0: String16,
+        param_value_1: String16,
+        param_value_2: String16,
+        param_value_3: String16,
+        param_visibility_0: Visibility,
+        param_visibility_1: Visibility,
+        param_visibility_2: Visibility,
+        param_visibility_3: Visibility,
+
+        // Text of each row of the TC history, newest first
+        history_row_0: String16,
+        history_row_1: String16,
+        history_row_2: String16,
+        history_row_3: String16,
+        history_row_4: String16,
+        history_row_5: String16,
+        sequence_text: String16,
+
+        // The operator's most recent status messages, newest first
+        status_row_0: String16,
+        status_row_1: String16,
+        status_row_2: String16,
+        status_row_3: String16,
+
+        trail_colour_text: String16,
+        trail_decay_text: String16,
+        plan_text: String16,
         render_pipeline: RenderPipeline,
         window_width: f64,
         window_height: f64
     }
 );
 
+// Holds the virtspace canvas, catching the mouse events on it to move the 
+// camera
+widget!(VirtSpaceView: MouseHandler);
+
+impl Template for VirtSpaceView {
+    fn template(self, _: Entity, _: &mut BuildContext) -> Self {
+        self.name("VirtSpaceView")
+    }
+}
+
+// Get the GUI state from the states context
+fn state<'a>(id: Entity, states: &'a mut StatesContext) -> &'a mut GuiState {
+    states.get_mut(id)
+}
+
+// Build the TC form, with a picker for the TC type and a row for each of its
+// parameters. The rows are filled in by the state from the parameter schema 
+// of the selected type, and rows which aren't needed are collapsed.
+fn tc_form(id: Entity, ctx: &mut BuildContext) -> Entity {
+    let mut form = Stack::create()
+        .orientation("vertical")
+        .attach(Grid::column(0))
+        .attach(Grid::row(1))
+        .margin((8.0, 0.0, 8.0, 0.0))
+        .child(Stack::create()
+            .orientation("horizontal")
+            .margin((0.0, 0.0, 0.0, 8.0))
+            .child(Button::create()
+                .text("<")
+                .on_click(move |states, _| -> bool {
+                    state(id, states).action(Action::PrevTcType);
+                    true
+                })
+                .build(ctx))
+            .child(TextBlock::create()
+                .width(250.0)
+                .margin((8.0, 0.0, 8.0, 0.0))
+                .vertical_alignment("center")
+                .text(("tc_type_text", id))
+                .build(ctx))
+            .child(Button::create()
+                .text(">")
+                .on_click(move |states, _| -> bool {
+                    state(id, states).action(Action::NextTcType);
+                    true
+                })
+                .build(ctx))
+            .build(ctx));
+
+    for i in 0..MAX_TC_PARAMS {
+        form = form.child(Stack::create()
+            .orientation("horizontal")
+            .margin((0.0, 0.0, 0.0, 4.0))
+            .visibility((&*format!("param_visibility_{}", i), id))
+            .child(TextBlock::create()
+                .width(240.0)
+                .vertical_alignment("center")
+                .text((&*format!("param_label_{}", i), id))
+                .build(ctx))
+            .child(TextBox::create()
+                .width(190.0)
+                .text((&*format!("param_value_{}", i), id))
+                .build(ctx))
+            .build(ctx));
+    }
+
+    form.build(ctx)
+}
+
+// Build the TC history panel, listing the TCs sent this session with buttons
+// to scroll through them and to resend or edit the selected one. Clicking a
+// row selects its TC.
+fn tc_history(id: Entity, ctx: &mut BuildContext) -> Entity {
+    let mut history = Stack::create()
+        .orientation("vertical")
+        .attach(Grid::column(2))
+        .attach(Grid::row(1))
+        .margin((8.0, 8.0, 8.0, 8.0))
+        .child(TextBlock::create()
+            .selector(Selector::from("text-block").class("header"))
+            .margin((0.0, 0.0, 0.0, 8.0))
+            .text("TC History")
+            .build(ctx));
+
+    for row in 0..HISTORY_ROWS {
+        history = history.child(Button::create()
+            .selector(Selector::from("button").class("history-row"))
+            .height(24.0)
+            .horizontal_alignment("stretch")
+            .text((&*format!("history_row_{}", row), id))
+            .on_click(move |states, _| -> bool {
+                state(id, states).action(Action::SelectHistory(row));
+                true
+            })
+            .build(ctx));
+    }
+
+    let buttons: [(&str, Action); 4] = [
+        ("Newer", Action::ScrollHistory(-1)),
+        ("Older", Action::ScrollHistory(1)),
+        ("Resend", Action::ResendTc),
+        ("Edit", Action::EditTc)
+    ];
+    let mut controls = Stack::create()
+        .orientation("horizontal")
+        .margin((0.0, 8.0, 0.0, 0.0));
+
+    for (text, action) in buttons.iter().cloned() {
+        controls = controls.child(Button::create()
+            .text(text)
+            .margin((0.0, 0.0, 8.0, 0.0))
+            .on_click(move |states, _| -> bool {
+                state(id, states).action(action);
+                true
+            })
+            .build(ctx));
+    }
+
+    history.child(controls.build(ctx)).build(ctx)
+}
+
+// Build the virtspace, with buttons over it for the camera's preset views, the
+// rover's trail and planning a path. Dragging over the view orbits or pans the
+// camera, or places waypoints while planning, and scrolling zooms it.
+fn virtspace(id: Entity, ctx: &mut BuildContext) -> Entity {
+    let views: [(&str, CameraMode); 3] = [
+        ("Free", CameraMode::Free),
+        ("Top", CameraMode::TopDown),
+        ("Chase", CameraMode::Chase)
+    ];
+    let mut view_buttons = Stack::create()
+        .orientation("horizontal")
+        .horizontal_alignment("start")
+        .vertical_alignment("start")
+        .margin((8.0, 8.0, 8.0, 8.0));
+
+    for (text, mode) in views.iter().cloned() {
+        view_buttons = view_buttons.child(Button::create()
+            .text(text)
+            .margin((0.0, 0.0, 8.0, 0.0))
+            .on_click(move |states, _| -> bool {
+                state(id, states).action(Action::CameraView(mode));
+                true
+            })
+            .build(ctx));
+    }
+
+    let view_buttons = view_buttons
+        .child(Button::create()
+            .text(("trail_colour_text", id))
+            .margin((16.0, 0.0, 8.0, 0.0))
+            .on_click(move |states, _| -> bool {
+                state(id, states).action(Action::TrailColour);
+                true
+            })
+            .build(ctx))
+        .child(Button::create()
+            .text(("trail_decay_text", id))
+            .margin((0.0, 0.0, 8.0, 0.0))
+            .on_click(move |states, _| -> bool {
+                state(id, states).action(Action::TrailDecay);
+                true
+            })
+            .build(ctx))
+        .child(Button::create()
+            .text("Clear trail")
+            .on_click(move |states, _| -> bool {
+                state(id, states).action(Action::ClearTrail);
+                true
+            })
+            .build(ctx))
+        .child(Button::create()
+            .text(("plan_text", id))
+            .margin((16.0, 0.0, 8.0, 0.0))
+            .on_click(move |states, _| -> bool {
+                state(id, states).action(Action::PlanPath);
+                true
+            })
+            .build(ctx))
+        .child(Button::create()
+            .text("Send path")
+            .margin((0.0, 0.0, 8.0, 0.0))
+            .on_click(move |states, _| -> bool {
+                state(id, states).action(Action::SendPath);
+                true
+            })
+            .build(ctx))
+        .child(Button::create()
+            .text("Clear path")
+            .on_click(move |states, _| -> bool {
+                state(id, states).action(Action::ClearPath);
+                true
+            })
+            .build(ctx));
+
+    VirtSpaceView::create()
+        .attach(Grid::column(1))
+        .attach(Grid::column_span(2))
+        .on_mouse_down(move |states, mouse| -> bool {
+            state(id, states).view_input().press(mouse.button);
+            true
+        })
+        .on_mouse_up(move |states, _| {
+            state(id, states).view_input().release();
+        })
+        .on_mouse_move(move |states, pos| -> bool {
+            state(id, states).view_input().move_to(pos.x, pos.y);
+            true
+        })
+        .on_scroll(move |states, delta| -> bool {
+            state(id, states).view_input().scroll(delta.y);
+            true
+        })
+        .child(Canvas::create()
+            .selector("virtspace")
+            .render_pipeline(id)
+            .build(ctx))
+        .child(view_buttons.build(ctx))
+        .build(ctx)
+}
+
+// Build the status log, showing the operator's most recent status messages
+// under the TM panel
+fn status_log(id: Entity, ctx: &mut BuildContext) -> Entity {
+    let mut stack = Stack::create()
+        .orientation("vertical")
+        .margin((0.0, 8.0, 0.0, 0.0))
+        .child(TextBlock::create()
+            .selector(Selector::from("text-block").class("header"))
+            .text("Status")
+            .build(ctx));
+
+    for row in 0..STATUS_ROWS {
+        stack = stack.child(TextBlock::create()
+            .margin((0.0, 4.0, 0.0, 0.0))
+            .text((&*format!("status_row_{}", row), id))
+            .build(ctx));
+    }
+
+    stack.build(ctx)
+}
+
 impl Template for MainView {
     fn template(self, id: Entity, ctx: &mut BuildContext) -> Self {
         self.name("MainView")
             .render_pipeline(RenderPipeline(Box::new(VirtSpacePipeline::default())))
+            .send_text("Send")
+            .run_text("Run")
+            // Escape aborts from anywhere in the window
+            .on_key_down(move |states, event| -> bool {
+                match event.key {
+                    Key::Escape => {
+                        state(id, states).action(Action::Abort);
+                        true
+                    },
+                    _ => false
+                }
+            })
             .child(
                 Grid::create()
                     .columns(
                         Columns::create()
-                            .repeat(450.0, 2)
+                            .repeat(PANEL_WIDTH, 2)
                             .column("stretch")
                             .build()
                     )
@@ -67,10 +365,27 @@ impl Template for MainView {
                                 .class("header"))
                             .text("TM: Telemetry")
                             .build(ctx))
+                        .child(TextBlock::create()
+                            .margin((0.0, 4.0, 0.0, 0.0))
+                            .text(("link_text", id))
+                            .build(ctx))
+                        .child(TextBlock::create()
+                            .margin((0.0, 4.0, 0.0, 0.0))
+                            .text(("mode_text", id))
+                            .build(ctx))
+                        .child(TextBlock::create()
+                            .margin((0.0, 4.0, 0.0, 0.0))
+                            .text(("pose_text", id))
+                            .build(ctx))
+                        .child(TextBlock::create()
+                            .margin((0.0, 4.0, 0.0, 0.0))
+                            .text(("health_text", id))
+                            .build(ctx))
+                        .child(status_log(id, ctx))
                         .build(ctx))
                     .child(Grid::create()
                         .columns(Columns::create()
-                            .column("auto")
+                            .column(PANEL_WIDTH)
                             .column("stretch")
                             .build())
                         .rows(Rows::create()
@@ -88,6 +403,59 @@ impl Template for MainView {
                             .margin((8.0, 8.0, 8.0, 8.0))
                             .text("TC: Telecommand")
                             .build(ctx))
+                        .child(tc_form(id, ctx))
+                        .child(Stack::create()
+                            .orientation("vertical")
+                            .attach(Grid::column(1))
+                            .attach(Grid::row(1))
+                            .margin((8.0, 0.0, 8.0, 0.0))
+                            .child(TextBox::create()
+                                .water_mark("Sequence file")
+                                .text(("sequence_path", id))
+                                .build(ctx))
+                            .child(Stack::create()
+                                .orientation("horizontal")
+                                .margin((0.0, 8.0, 0.0, 8.0))
+                                .child(Button::create()
+                                    .text(("run_text", id))
+                                    .margin((0.0, 0.0, 8.0, 0.0))
+                                    .on_click(move |states, _| -> bool {
+                                        state(id, states)
+                                            .action(Action::RunSequence);
+                                        true
+                                    })
+                                    .build(ctx))
+                                .child(Button::create()
+                                    .text("Pause")
+                                    .margin((0.0, 0.0, 8.0, 0.0))
+                                    .on_click(move |states, _| -> bool {
+                                        state(id, states)
+                                            .action(Action::PauseSequence);
+                                        true
+                                    })
+                                    .build(ctx))
+                                .child(Button::create()
+                                    .text("Resume")
+                                    .margin((0.0, 0.0, 8.0, 0.0))
+                                    .on_click(move |states, _| -> bool {
+                                        state(id, states)
+                                            .action(Action::ResumeSequence);
+                                        true
+                                    })
+                                    .build(ctx))
+                                .child(Button::create()
+                                    .text("Stop")
+                                    .on_click(move |states, _| -> bool {
+                                        state(id, states)
+                                            .action(Action::StopSequence);
+                                        true
+                                    })
+                                    .build(ctx))
+                                .build(ctx))
+                            .child(TextBlock::create()
+                                .text(("sequence_text", id))
+                                .build(ctx))
+                            .build(ctx))
                         .child(Button::create()
                             .selector(Selector::from("button").class("abort"))
                             .attach(Grid::column(0))
@@ -96,6 +464,10 @@ impl Template for MainView {
                             .horizontal_alignment("start")
                             .vertical_alignment("center")
                             .text("Abort")
+                            .on_click(move |states, _| -> bool {
+                                state(id, states).action(Action::Abort);
+                                true
+                            })
                             .build(ctx))
                         .child(Button::create()
                             .selector(Selector::from("button").class("send"))
@@ -104,15 +476,15 @@ impl Template for MainView {
                             .margin((8.0, 8.0, 8.0, 8.0))
                             .horizontal_alignment("end")
                             .vertical_alignment("center")
-                            .text("Send")
+                            .text(("send_text", id))
+                            .on_click(move |states, _| -> bool {
+                                state(id, states).action(Action::Send);
+                                true
+                            })
                             .build(ctx))
                         .build(ctx))
-                    .child(Canvas::create()
-                        .selector("virtspace")
-                        .attach(Grid::column(1))
-                        .attach(Grid::column_span(2))
-                        .render_pipeline(id)
-                        .build(ctx))
+                    .child(tc_history(id, ctx))
+                    .child(virtspace(id, ctx))
                     .build(ctx)
             )
     }
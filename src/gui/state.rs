@@ -1,9 +1,286 @@
 use orbtk::{prelude::*, shell::ShellRequest};
 use std::sync::{Arc, Mutex};
+use std::collections::VecDeque;
 use chrono::{DateTime, Utc};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::thread;
-use crate::virtspace::VirtSpacePipeline;
+use std::env;
+use vek::Vec2;
+use crate::virtspace::{
+    VirtSpacePipeline, 
+    pipeline::{RoverPose, TrailConfig, TrailColour}, 
+    camera::{Camera, CameraMode},
+    path_plan::PathPlan,
+    terrain::{Dem, Terrain}};
+use autonomy_control::tm_tc_interface::{
+    TmTcIf, TmTcIfConfig, TmTcData, LinkEvent};
+use autonomy_control::tm_deconstructor::{
+    TmDeconstructor, TmRoverPose, TmRoverHealth, TmRoverMode, TmSchedule};
+use autonomy_control::tc_constructor::{
+    TcConstructor, TcRegistry, TcTypeInfo, ArmedTc, SentTc, TcAbort, 
+    TcNavigateWaypoints, Waypoint, ARM_TIMEOUT_S};
+use autonomy_control::tc_sequence::{
+    ArmedSequence, ConfirmedSequence, TcSequenceRunner, SequenceState};
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+/// Number of parameter fields in the TC form. TC types with more parameters
+/// than this can't be sent from the form.
+pub const MAX_TC_PARAMS: usize = 4;
+
+/// Number of rows shown in the TC history at once
+pub const HISTORY_ROWS: usize = 6;
+
+/// Number of the operator's most recent status messages shown at once
+pub const STATUS_ROWS: usize = 4;
+
+/// Environment variable giving the path of the DEM file of the rover's site,
+/// see `virtspace::terrain`. The ground is drawn flat if it isn't set.
+const DEM_PATH_VAR: &str = "VIRTSPACE_DEM";
+
+/// Width of the TM panel and of the TC panel's columns. The virtspace is to
+/// the right of the TM panel.
+pub const PANEL_WIDTH: f64 = 450.0;
+
+/// Ages at which the rover's trail fades out that the operator can pick
+/// from, None keeping the whole trail
+const TRAIL_DECAY_OPTIONS_S: [Option<f32>; 4] = 
+    [Some(30.0), Some(120.0), Some(600.0), None];
+
+// ---------------------------------------------------------------------------
+// ACTIONS
+// ---------------------------------------------------------------------------
+
+/// Actions requested by the operator through the GUI, carried out on the next
+/// update.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Stop the rover immediately
+    Abort,
+
+    /// Send the TC entered in the TC form, or confirm it if it's armed
+    Send,
+
+    /// Select the previous TC type in the TC form
+    PrevTcType,
+
+    /// Select the next TC type in the TC form
+    NextTcType,
+
+    /// Select the TC shown in a row of the TC history
+    SelectHistory(usize),
+
+    /// Scroll the TC history by a number of rows, positive to show older TCs
+    ScrollHistory(isize),
+
+    /// Send the TC selected in the TC history again
+    ResendTc,
+
+    /// Copy the TC selected in the TC history into the TC form
+    EditTc,
+
+    /// Load the sequence file given in the sequence path box and start it, or
+    /// confirm it if it's armed
+    RunSequence,
+
+    /// Stop sending the running sequence's TCs until it's resumed
+    PauseSequence,
+
+    /// Carry on with a paused sequence
+    ResumeSequence,
+
+    /// Stop the running sequence for good
+    StopSequence,
+
+    /// Switch the virtspace camera to one of its preset views
+    CameraView(CameraMode),
+
+    /// Switch the rover's trail between showing speed and time
+    TrailColour,
+
+    /// Pick the next of the ages at which the rover's trail fades out
+    TrailDecay,
+
+    /// Remove the rover's trail drawn so far
+    ClearTrail,
+
+    /// Start or stop planning a path by placing waypoints on the virtspace
+    PlanPath,
+
+    /// Send the planned path to the rover as a `TcNavigateWaypoints`
+    SendPath,
+
+    /// Remove all the planned path's waypoints
+    ClearPath
+}
+
+// ---------------------------------------------------------------------------
+// VIEW INPUT
+// ---------------------------------------------------------------------------
+
+/// A change to the planned path requested with the mouse, at a point on the
+/// view in pixels from its top left corner
+#[derive(Clone, Copy, Debug)]
+enum PathEdit {
+    Press(Vec2<f32>),
+    Drag(Vec2<f32>),
+    Release,
+    Delete(Vec2<f32>)
+}
+
+/// Mouse input on the virtspace, gathered as it arrives and applied to the
+/// camera and planned path on the next update. Dragging with the left button
+/// orbits the camera, dragging with any other button pans it, and scrolling
+/// zooms. While planning a path the left button instead adds and drags
+/// waypoints and the right button deletes them, leaving the middle button to
+/// pan.
+#[derive(Default)]
+pub struct ViewInput {
+    /// The button held down while dragging over the view
+    drag_button: Option<MouseButton>,
+
+    /// Position of the mouse on the view when it last moved
+    position: Option<Vec2<f32>>,
+
+    /// Mouse movement since the last update, in pixels
+    orbit_px: (f32, f32),
+    pan_px: (f32, f32),
+
+    /// Scroll wheel steps since the last update, positive to zoom in
+    zoom_steps: f32,
+
+    /// Preset view requested since the last update
+    mode: Option<CameraMode>,
+
+    /// True while the operator is planning a path
+    planning: bool,
+
+    /// Changes to the planned path since the last update
+    path_edits: Vec<PathEdit>
+}
+
+impl ViewInput {
+
+    /// Start dragging over the view with a mouse button
+    pub fn press(&mut self, button: MouseButton) {
+        self.drag_button = Some(button);
+
+        if let (true, Some(p)) = (self.planning, self.position) {
+            match button {
+                MouseButton::Left => self.path_edits.push(PathEdit::Press(p)),
+                MouseButton::Right => self.path_edits.push(PathEdit::Delete(p)),
+                _ => ()
+            }
+        }
+    }
+
+    /// Stop dragging over the view
+    pub fn release(&mut self) {
+        if self.drag_button == Some(MouseButton::Left) {
+            self.path_edits.push(PathEdit::Release);
+        }
+
+        self.drag_button = None;
+    }
+
+    /// Track the mouse moving over the window, which moves the camera or the
+    /// waypoint being dragged if a button is held down
+    pub fn move_to(&mut self, x: f64, y: f64) {
+        // The view is to the right of the TM panel, at the top of the window
+        let position = Vec2::new((x - PANEL_WIDTH) as f32, y as f32);
+
+        if let (Some(button), Some(last)) = (self.drag_button, self.position) {
+            let delta = position - last;
+            let total = match (self.planning, button) {
+                (true, MouseButton::Left) => {
+                    self.path_edits.push(PathEdit::Drag(position));
+                    None
+                },
+                (true, MouseButton::Right) => None,
+                (false, MouseButton::Left) => Some(&mut self.orbit_px),
+                _ => Some(&mut self.pan_px)
+            };
+
+            if let Some(t) = total {
+                t.0 += delta.x;
+                t.1 += delta.y;
+            }
+        }
+
+        self.position = Some(position);
+    }
+
+    /// Zoom by a scroll of the mouse wheel
+    pub fn scroll(&mut self, delta: f64) {
+        self.zoom_steps += delta as f32;
+    }
+
+    /// Move the camera and edit the planned path by the input gathered since
+    /// the last update, then clear it. `rover` is the rover's current pose,
+    /// if it's known, `size` is the size of the view and `dem` is the 
+    /// terrain, if any.
+    fn apply(
+        &mut self, camera: &mut Camera, path_plan: &mut PathPlan,
+        rover: Option<RoverPose>, size: Vec2<f32>, dem: Option<&Dem>) {
+
+        if let Some(mode) = self.mode.take() {
+            camera.set_mode(mode, rover);
+        }
+
+        camera.orbit(self.orbit_px.0, self.orbit_px.1);
+        camera.pan(self.pan_px.0, self.pan_px.1, rover);
+        camera.zoom(self.zoom_steps);
+
+        self.orbit_px = (0.0, 0.0);
+        self.pan_px = (0.0, 0.0);
+        self.zoom_steps = 0.0;
+
+        let mvp = camera.mvp(size.x, size.y, rover);
+
+        for edit in self.path_edits.drain(..) {
+            match edit {
+                PathEdit::Press(p) => path_plan.press(&mvp, size, p, dem),
+                PathEdit::Drag(p) => path_plan.drag(&mvp, size, p, dem),
+                PathEdit::Release => path_plan.release(),
+                PathEdit::Delete(p) => path_plan.delete(&mvp, size, p, dem)
+            }
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// STATUS LOG
+// ---------------------------------------------------------------------------
+
+/// Messages telling the operator what the GUI has done, or couldn't do, shown
+/// in the TM panel. Only the most recent `STATUS_ROWS` are kept.
+#[derive(Default)]
+struct StatusLog {
+    messages: VecDeque<String>
+}
+
+impl StatusLog {
+
+    /// Add a message about something which has happened
+    fn info(&mut self, message: impl Into<String>) {
+        if self.messages.len() >= STATUS_ROWS {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(message.into());
+    }
+
+    /// Add a message about something which went wrong
+    fn error(&mut self, message: impl Into<String>) {
+        self.info(format!("Error: {}", message.into()));
+    }
+
+    /// Get the text of a row of the log, where row 0 is the newest message
+    fn row(&self, row: usize) -> String {
+        self.messages.iter().rev().nth(row).cloned().unwrap_or_default()
+    }
+}
 
 // ---------------------------------------------------------------------------
 // ORBTK GUI STATE
@@ -14,7 +291,28 @@ pub struct GuiState {
     updater_thread: Option<thread::JoinHandle<()>>,
     exit_updater: Arc<Mutex<bool>>,
     current_time_utc: DateTime<Utc>,
-    frame_counter: u64
+    frame_counter: u64,
+    actions: VecDeque<Action>,
+    tc_cstr: Option<TcConstructor>,
+    tm_dcstr: TmDeconstructor,
+    status: StatusLog,
+    rover_pose: Option<TmRoverPose>,
+    new_rover_poses: Vec<TmRoverPose>,
+    rover_health: Option<TmRoverHealth>,
+    tc_registry: TcRegistry,
+    selected_tc: usize,
+    tc_form_changed: bool,
+    tc_form_values: Option<Vec<String>>,
+    history_selected: Option<usize>,
+    history_scroll: usize,
+    armed_tc: Option<ArmedTc>,
+    armed_sequence: Option<ArmedSequence>,
+    sequence_runner: Option<TcSequenceRunner>,
+    view_input: ViewInput,
+    trail_config: TrailConfig,
+    clear_trail: bool,
+    path_plan: PathPlan,
+    new_terrain: Option<Terrain>
 }
 
 impl Default for GuiState {
@@ -23,11 +321,673 @@ impl Default for GuiState {
             updater_thread: None,
             exit_updater: Arc::new(Mutex::new(false)),
             current_time_utc: Utc::now(),
-            frame_counter: 0
+            frame_counter: 0,
+            actions: VecDeque::new(),
+            tc_cstr: None,
+            tm_dcstr: new_tm_dcstr(),
+            status: StatusLog::default(),
+            rover_pose: None,
+            new_rover_poses: vec![],
+            rover_health: None,
+            tc_registry: TcRegistry::new(),
+            selected_tc: 0,
+            tc_form_changed: true,
+            tc_form_values: None,
+            history_selected: None,
+            history_scroll: 0,
+            armed_tc: None,
+            armed_sequence: None,
+            sequence_runner: None,
+            view_input: ViewInput::default(),
+            trail_config: TrailConfig::default(),
+            clear_trail: false,
+            path_plan: PathPlan::default(),
+            new_terrain: None
         }
     }
 }
 
+/// Create the deconstructor for the TMs the GUI shows. The rover's mode is
+/// tracked by the interface itself so its TMs are ignored here.
+fn new_tm_dcstr() -> TmDeconstructor {
+    let mut tm_dcstr = TmDeconstructor::new();
+
+    tm_dcstr.register_queue::<TmRoverPose>();
+    tm_dcstr.register_queue::<TmRoverHealth>();
+    tm_dcstr.register_queue::<TmSchedule>();
+    tm_dcstr.ignore::<TmRoverMode>();
+
+    tm_dcstr
+}
+
+impl GuiState {
+
+    /// Request an action to be carried out on the next update, after any
+    /// already requested. An abort discards everything requested before it,
+    /// so nothing clicked just before it can start the rover moving again
+    /// afterwards, and can't itself be lost.
+    pub fn action(&mut self, action: impl Into<Option<Action>>) {
+        match action.into() {
+            Some(Action::Abort) => {
+                self.actions.clear();
+                self.actions.push_back(Action::Abort);
+            },
+            Some(a) => self.actions.push_back(a),
+            None => ()
+        }
+    }
+
+    /// Get the mouse input for the virtspace, to add to it as it arrives.
+    pub fn view_input(&mut self) -> &mut ViewInput {
+        &mut self.view_input
+    }
+
+    /// Start the link to the rover, configured from the environment (see
+    /// `TmTcIfConfig::from_env`). The interface keeps trying to connect in
+    /// the background until it's stopped.
+    fn start_link(&mut self) {
+        let config = match TmTcIfConfig::from_env() {
+            Ok(c) => c,
+            Err(e) => {
+                self.status.error(format!(
+                    "Cannot start the link to the rover: {}", e));
+                return
+            }
+        };
+
+        self.status.info(format!(
+            "Connecting to rover at {}:{}", 
+            config.rover_host, config.rover_port));
+        self.tc_cstr = Some(TcConstructor::new(TmTcIf::start(config)));
+    }
+
+    /// Stop the link to the rover, if it's running, returning what happened
+    /// to any TCs it still had.
+    fn stop_link(&mut self) -> Option<String> {
+        let tc_cstr = self.tc_cstr.take()?;
+
+        Some(match tc_cstr.stop() {
+            Ok(report) => format!("Stopped TmTcIf: {:?}", report),
+            Err(e) => format!("Cannot stop TmTcIf: {}", e)
+        })
+    }
+
+    /// Load the DEM of the rover's site given by `DEM_PATH_VAR`, if it's set,
+    /// to be passed on to the virtspace and drawn as the ground.
+    fn load_terrain(&mut self) {
+        let path = match env::var(DEM_PATH_VAR) {
+            Ok(p) => p,
+            Err(_) => return
+        };
+
+        match Dem::from_file(&path) {
+            Ok(dem) => {
+                self.status.info(format!(
+                    "Loaded DEM {} with {} by {} heights", 
+                    path, dem.num_x(), dem.num_y()));
+                self.new_terrain = Some(Terrain::new(dem));
+            },
+            Err(e) => self.status.error(format!(
+                "Cannot load DEM {}: {}", path, e))
+        }
+    }
+
+    /// Read the TMs and link events received since the last update, keeping
+    /// the latest pose and health. New poses are also kept to be passed on to
+    /// the virtspace.
+    fn process_tms(&mut self) {
+        let tm_tc_if = match &self.tc_cstr {
+            Some(c) => c.tm_tc_if(),
+            None => return
+        };
+
+        if let Err(e) = self.tm_dcstr.process(tm_tc_if) {
+            self.status.error(format!("Cannot read TMs: {}", e));
+
+            // Stop what's left of the link so anything it couldn't deliver
+            // is reported
+            if e.is_fatal() {
+                self.status.error("The link to the rover has stopped");
+                if let Some(report) = self.stop_link() {
+                    self.status.info(report);
+                }
+                return
+            }
+        }
+
+        while let Some(event) = tm_tc_if.get_link_event() {
+            match event {
+                LinkEvent::StateChanged(s) => self.status.info(format!(
+                    "Link {}", s)),
+                LinkEvent::LinkLost => self.status.error(
+                    "Link to the rover lost"),
+                LinkEvent::LinkRestored => self.status.info(
+                    "Link to the rover restored")
+            }
+        }
+
+        while let Ok(Some(pose)) = self.tm_dcstr.get_pending::<TmRoverPose>() {
+            self.rover_pose = Some(pose.clone());
+            self.new_rover_poses.push(pose);
+        }
+
+        while let Ok(Some(health)) = 
+            self.tm_dcstr.get_pending::<TmRoverHealth>() {
+
+            self.rover_health = Some(health);
+        }
+
+        while let Ok(Some(schedule)) = 
+            self.tm_dcstr.get_pending::<TmSchedule>() {
+
+            self.status.info(match schedule.entries.first() {
+                Some(e) => format!(
+                    "Rover schedule has {} TCs, first #{} {}", 
+                    schedule.entries.len(), e.frame_counter, e.data_type_id),
+                None => String::from("Rover schedule is empty")
+            });
+        }
+
+        // Unknown TMs have already been reported by `process`
+        while self.tm_dcstr.get_unknown_tm().is_some() {}
+    }
+
+    /// Describe the link and the latest TM for the TM panel
+    fn update_tm_panel(&self, ctx: &mut Context) {
+        let (link, mode) = match &self.tc_cstr {
+            Some(c) => (
+                format!("{}", c.tm_tc_if().link_state()),
+                match c.tm_tc_if().rover_mode() {
+                    Some(m) => format!("{}", m),
+                    None => String::from("Unknown")
+                }),
+            None => (String::from("Not started"), String::from("Unknown"))
+        };
+
+        ctx.widget().set(
+            "link_text", String16::from(format!("Link: {}", link)));
+        ctx.widget().set(
+            "mode_text", String16::from(format!("Mode: {}", mode)));
+
+        let pose = match &self.rover_pose {
+            Some(p) => {
+                // Heading is the rotation about the z axis
+                let [x, y, z, w] = p.attitude_q;
+                let heading_rad = 
+                    (2.0 * (w * z + x * y)).atan2(1.0 - 2.0 * (y * y + z * z));
+
+                format!(
+                    "Position: {:.2}, {:.2}, {:.2} m, heading {:.1} deg",
+                    p.position_m[0], p.position_m[1], p.position_m[2],
+                    heading_rad.to_degrees())
+            },
+            None => String::from("Position: Unknown")
+        };
+        ctx.widget().set("pose_text", String16::from(pose));
+
+        let health = match &self.rover_health {
+            Some(h) => format!(
+                "Battery: {:.1} V ({:.0} %), CPU: {:.1} C",
+                h.battery_voltage_v, h.battery_charge_pct, h.cpu_temp_c),
+            None => String::from("Battery: Unknown")
+        };
+        ctx.widget().set("health_text", String16::from(health));
+    }
+
+    /// Send an abort to the rover. The interface sends it ahead of any other
+    /// queued TCs and keeps resending it until the rover acknowledges it. Any
+    /// running sequence is stopped too, so it can't carry on driving the rover
+    /// afterwards, and anything armed is disarmed.
+    fn abort(&mut self) {
+        if let Some(r) = self.sequence_runner.as_mut() {
+            r.abort();
+        }
+
+        self.armed_tc = None;
+        self.armed_sequence = None;
+
+        let tc_cstr = match self.tc_cstr.as_mut() {
+            Some(c) => c,
+            None => {
+                self.status.error(
+                    "Cannot abort, there is no link to the rover");
+                return
+            }
+        };
+
+        match tc_cstr.build_and_send(TcAbort::new()) {
+            Ok(_) => self.status.info("Abort sent to rover"),
+            Err(e) => self.status.error(format!("Cannot send abort: {}", e))
+        }
+    }
+
+    /// Select another TC type in the TC form, `step` types on from the
+    /// current one. Anything armed is disarmed, so that Send can only
+    /// confirm the type that's shown.
+    fn select_tc_type(&mut self, step: isize) {
+        let num_types = self.tc_registry.types().len() as isize;
+        self.selected_tc = 
+            (self.selected_tc as isize + step).rem_euclid(num_types) as usize;
+        self.tc_form_changed = true;
+        self.armed_tc = None;
+    }
+
+    /// Fill in the TC form for the selected type, with a labelled field for
+    /// each parameter. The fields start at the parameters' default values
+    /// unless values have been given by `edit_tc`.
+    fn update_tc_form(&mut self, ctx: &mut Context) {
+        let type_info = &self.tc_registry.types()[self.selected_tc];
+        let values = self.tc_form_values.take();
+
+        ctx.widget().set("tc_type_text", String16::from(type_info.type_id));
+
+        if type_info.params.len() > MAX_TC_PARAMS {
+            self.status.error(format!(
+                "{} has {} parameters but the TC form only has room for {}",
+                type_info.type_id, type_info.params.len(), MAX_TC_PARAMS));
+        }
+
+        for i in 0..MAX_TC_PARAMS {
+            let (label, value, visibility) = match type_info.params.get(i) {
+                Some(p) => {
+                    let value = match values.as_ref().and_then(|v| v.get(i)) {
+                        Some(v) => v.clone(),
+                        None => String::from(p.default)
+                    };
+                    (p.to_string(), value, Visibility::Visible)
+                },
+                None => (String::new(), String::new(), Visibility::Collapsed)
+            };
+
+            ctx.widget().set(
+                &format!("param_label_{}", i), String16::from(label));
+            ctx.widget().set(
+                &format!("param_value_{}", i), String16::from(value));
+            ctx.widget().set(&format!("param_visibility_{}", i), visibility);
+        }
+
+        self.tc_form_changed = false;
+    }
+
+    /// Send the selected type of TC with the parameter values entered in the
+    /// TC form. If a TC is armed it's confirmed instead.
+    fn send_form(&mut self, values: &[String]) {
+        // A second click confirms the armed TC, if it hasn't expired
+        if self.confirm_armed() {
+            return
+        }
+
+        let type_info = self.tc_registry.types()[self.selected_tc];
+
+        match type_info.params_from_text(values) {
+            Ok(p) => self.send_tc(type_info, p),
+            Err(e) => self.status.error(format!(
+                "Cannot send {}: {}", type_info.type_id, e))
+        }
+    }
+
+    /// Send the armed TC, returning false if there wasn't one or it had
+    /// expired. Either way it's no longer armed afterwards.
+    fn confirm_armed(&mut self) -> bool {
+        let armed = match self.armed_tc.take() {
+            Some(a) if !a.is_expired() => a,
+            _ => return false
+        };
+
+        let tc_cstr = match self.tc_cstr.as_mut() {
+            Some(c) => c,
+            None => {
+                self.status.error(format!(
+                    "Cannot send {}, there is no link to the rover",
+                    armed.type_id()));
+                return true
+            }
+        };
+
+        let armed_type_id = armed.type_id();
+        match tc_cstr.confirm(armed) {
+            Ok(_) => self.status.info(format!(
+                "{} confirmed and sent", armed_type_id)),
+            Err(e) => self.status.error(format!(
+                "Cannot send {}: {}", armed_type_id, e))
+        }
+
+        true
+    }
+
+    /// Send a TC of the given type. A critical TC is armed rather than sent,
+    /// and is only sent if Send is clicked before the arming times out.
+    fn send_tc(&mut self, type_info: TcTypeInfo, params: serde_json::Value) {
+        let type_id = type_info.type_id;
+
+        let tc_cstr = match self.tc_cstr.as_mut() {
+            Some(c) => c,
+            None => {
+                self.status.error(format!(
+                    "Cannot send {}, there is no link to the rover",
+                    type_id));
+                return
+            }
+        };
+
+        let critical = match type_info.is_critical(&params) {
+            Ok(c) => c,
+            Err(e) => {
+                self.status.error(format!("Cannot send {}: {}", type_id, e));
+                return
+            }
+        };
+
+        if critical {
+            match type_info.arm(tc_cstr, params) {
+                Ok(a) => {
+                    self.status.info(format!(
+                        "{} armed, click Send within {} s to confirm",
+                        type_id, ARM_TIMEOUT_S));
+                    self.armed_tc = Some(a);
+                },
+                Err(e) => self.status.error(format!(
+                    "Cannot arm {}: {}", type_id, e))
+            }
+        }
+        else {
+            match type_info.send(tc_cstr, params) {
+                Ok(_) => self.status.info(format!("{} sent", type_id)),
+                Err(e) => self.status.error(format!(
+                    "Cannot send {}: {}", type_id, e))
+            }
+        }
+    }
+
+    /// Get the TC selected in the TC history along with its type, or None if
+    /// nothing is selected
+    fn selected_history_tc(&mut self) -> Option<(SentTc, TcTypeInfo)> {
+        let index = self.history_selected?;
+        let sent_tc = self.tc_cstr.as_ref()?.history().get(index)?.clone();
+
+        match self.tc_registry.get(sent_tc.type_id) {
+            Ok(t) => Some((sent_tc, *t)),
+            Err(e) => {
+                self.status.error(format!("Cannot use the selected TC: {}", e));
+                None
+            }
+        }
+    }
+
+    /// Send the TC selected in the TC history again, with the same 
+    /// parameters. A scheduled TC is sent to be executed straight away.
+    fn resend_tc(&mut self) {
+        match self.selected_history_tc() {
+            Some((sent_tc, type_info)) => {
+                self.send_tc(type_info, sent_tc.params)
+            },
+            None => self.status.error("Select a TC in the history to resend it")
+        }
+    }
+
+    /// Copy the TC selected in the TC history into the TC form, so it can be
+    /// changed before it's sent again.
+    fn edit_tc(&mut self) {
+        let (sent_tc, type_info) = match self.selected_history_tc() {
+            Some(s) => s,
+            None => {
+                self.status.error("Select a TC in the history to edit it");
+                return
+            }
+        };
+
+        self.selected_tc = self.tc_registry.types().iter()
+            .position(|t| t.type_id == type_info.type_id)
+            .unwrap_or(0);
+        self.tc_form_values = Some(type_info.params.iter()
+            .map(|p| p.format_value(&sent_tc.params[p.name]))
+            .collect());
+        self.tc_form_changed = true;
+        self.armed_tc = None;
+    }
+
+    /// Select the TC shown in a row of the TC history, where row 0 is the
+    /// newest TC shown. Selecting the selected TC again deselects it.
+    fn select_history_row(&mut self, row: usize) {
+        let num_sent = match &self.tc_cstr {
+            Some(c) => c.history().len(),
+            None => 0
+        };
+
+        let index = num_sent.checked_sub(self.history_scroll + row + 1);
+
+        self.history_selected = match index {
+            Some(i) if self.history_selected != Some(i) => Some(i),
+            _ => None
+        };
+    }
+
+    /// Scroll the TC history by `step` rows, positive to show older TCs.
+    fn scroll_history(&mut self, step: isize) {
+        let num_sent = match &self.tc_cstr {
+            Some(c) => c.history().len(),
+            None => 0
+        };
+        let max_scroll = num_sent.saturating_sub(HISTORY_ROWS) as isize;
+
+        self.history_scroll = (self.history_scroll as isize + step)
+            .max(0)
+            .min(max_scroll) as usize;
+    }
+
+    /// Show the TC history, newest first, marking the selected TC.
+    fn update_history(&mut self, ctx: &mut Context) {
+        let history = match &self.tc_cstr {
+            Some(c) => c.history(),
+            None => &[]
+        };
+
+        for row in 0..HISTORY_ROWS {
+            let index = history.len()
+                .checked_sub(self.history_scroll + row + 1);
+
+            let text = match index.map(|i| (i, &history[i])) {
+                Some((i, sent_tc)) => {
+                    let marker = match self.history_selected {
+                        Some(s) if s == i => ">",
+                        _ => " "
+                    };
+                    format!("{} {}", marker, sent_tc)
+                },
+                None => String::new()
+            };
+
+            ctx.widget().set(
+                &format!("history_row_{}", row), String16::from(text));
+        }
+    }
+
+    /// Load a sequence file and start running it, replacing any sequence
+    /// which has already finished. A sequence containing critical TCs is
+    /// armed, and only started if this is called again before the arming
+    /// times out. While the running sequence is waiting for a critical step
+    /// to be confirmed this confirms the step instead.
+    fn run_sequence(&mut self, path: &str) {
+        if let Some(r) = self.sequence_runner.as_mut() {
+            if *r.state() == SequenceState::AwaitingConfirmation {
+                match self.tc_cstr.as_mut() {
+                    Some(c) => match r.confirm_step(c) {
+                        Ok(_) => self.status.info("Sequence step confirmed"),
+                        Err(e) => self.status.error(format!(
+                            "Cannot confirm step: {}", e))
+                    },
+                    None => self.status.error(
+                        "Cannot confirm step, there is no link to the rover")
+                }
+                return
+            }
+
+            if !r.state().is_finished() {
+                self.status.error(format!(
+                    "Cannot run {}, a sequence is already running", path));
+                return
+            }
+        }
+
+        // A second click confirms the armed sequence, if it hasn't expired.
+        // If it has the file is loaded and armed again.
+        if let Some(a) = self.armed_sequence.take() {
+            match a.confirm() {
+                Ok(s) => {
+                    self.status.info(format!("Running sequence {}", s.name()));
+                    self.sequence_runner = Some(TcSequenceRunner::new(s));
+                    return
+                },
+                Err(e) => self.status.error(format!("{}", e))
+            }
+        }
+
+        match TcConstructor::load_sequence(path) {
+            Ok(s) if s.is_critical() => {
+                self.status.info(format!(
+                    "Sequence {} contains critical TCs, click Run again \
+                    within {} s to confirm", s.name(), ARM_TIMEOUT_S));
+                self.armed_sequence = Some(s.arm());
+            },
+            Ok(s) => match ConfirmedSequence::from_non_critical(s) {
+                Ok(s) => {
+                    self.status.info(format!(
+                        "Running sequence {} from {}", s.name(), path));
+                    self.sequence_runner = Some(TcSequenceRunner::new(s));
+                },
+                Err(e) => self.status.error(format!(
+                    "Cannot run sequence {}: {}", path, e))
+            },
+            Err(e) => self.status.error(format!(
+                "Cannot load sequence {}: {}", path, e))
+        }
+    }
+
+    /// Disarm anything which wasn't confirmed in time, and get the text for
+    /// the Send and Run buttons, which count down while something is armed.
+    fn update_armed(&mut self) -> (String, String) {
+        let send_text = match &self.armed_tc {
+            Some(a) if a.is_expired() => {
+                self.status.info(format!(
+                    "{} disarmed, it wasn't confirmed", a.type_id()));
+                self.armed_tc = None;
+                String::from("Send")
+            },
+            Some(a) => format!(
+                "Confirm {} ({} s)", a.type_id(), a.time_left().as_secs() + 1),
+            None => String::from("Send")
+        };
+
+        // A sequence step waiting to be confirmed is confirmed with Run
+        let step_time_left = self.sequence_runner.as_ref()
+            .and_then(|r| r.confirm_time_left());
+
+        let run_text = match (step_time_left, &self.armed_sequence) {
+            (Some(t), _) => format!("Confirm step ({} s)", t.as_secs() + 1),
+            (None, Some(a)) if a.is_expired() => {
+                self.status.info(format!(
+                    "Sequence {} disarmed, it wasn't confirmed", a.name()));
+                self.armed_sequence = None;
+                String::from("Run")
+            },
+            (None, Some(a)) => format!(
+                "Confirm run ({} s)", a.time_left().as_secs() + 1),
+            (None, None) => String::from("Run")
+        };
+
+        (send_text, run_text)
+    }
+
+    /// Send the running sequence's next TC if it's due, and describe where
+    /// the sequence has got to.
+    fn update_sequence(&mut self) -> String {
+        let runner = match self.sequence_runner.as_mut() {
+            Some(r) => r,
+            None => return String::from("No sequence loaded")
+        };
+
+        if let Some(tc_cstr) = self.tc_cstr.as_mut() {
+            runner.poll(tc_cstr);
+        }
+
+        format!("{}", runner.progress())
+    }
+
+    /// Switch the rover's trail between being coloured by speed and by time
+    fn toggle_trail_colour(&mut self) {
+        self.trail_config.colour = match self.trail_config.colour {
+            TrailColour::Speed => TrailColour::Time,
+            TrailColour::Time => TrailColour::Speed
+        };
+    }
+
+    /// Pick the next of the ages at which the rover's trail fades out
+    fn next_trail_decay(&mut self) {
+        let current = TRAIL_DECAY_OPTIONS_S.iter()
+            .position(|d| *d == self.trail_config.decay_s)
+            .unwrap_or(0);
+
+        self.trail_config.decay_s = 
+            TRAIL_DECAY_OPTIONS_S[(current + 1) % TRAIL_DECAY_OPTIONS_S.len()];
+    }
+
+    /// Send the planned path to the rover. Like any critical TC the path is
+    /// only armed at first, and is sent if Send path (or Send) is clicked
+    /// again before the arming times out.
+    fn send_path(&mut self) {
+        // A second click confirms the armed path, if it hasn't expired
+        if self.is_path_armed() && self.confirm_armed() {
+            return
+        }
+
+        let waypoints = self.path_plan.waypoints().iter()
+            .map(|w| Waypoint::new(w.x as f64, w.y as f64))
+            .collect();
+
+        let params = match TcNavigateWaypoints::new(waypoints)
+            .and_then(|tc| Ok(serde_json::to_value(tc)?)) {
+            Ok(p) => p,
+            Err(e) => {
+                self.status.error(format!(
+                    "Cannot send the planned path: {}", e));
+                return
+            }
+        };
+
+        let type_info = match self.tc_registry.get(TcNavigateWaypoints::TYPE_ID) {
+            Ok(t) => *t,
+            Err(e) => {
+                self.status.error(format!(
+                    "Cannot send the planned path: {}", e));
+                return
+            }
+        };
+
+        self.send_tc(type_info, params);
+    }
+
+    /// Returns true if the armed TC is the planned path
+    fn is_path_armed(&self) -> bool {
+        match &self.armed_tc {
+            Some(a) => a.type_id() == TcNavigateWaypoints::TYPE_ID,
+            None => false
+        }
+    }
+
+    /// Get the text of the trail buttons, which show the trail's settings
+    fn trail_texts(&self) -> (String, String) {
+        let colour_text = format!("Trail: {:?}", self.trail_config.colour);
+
+        let decay_text = match self.trail_config.decay_s {
+            Some(d) if d >= 60.0 => format!("Fade: {} min", d / 60.0),
+            Some(d) => format!("Fade: {} s", d),
+            None => String::from("Fade: off")
+        };
+
+        (colour_text, decay_text)
+    }
+}
+
 impl State for GuiState {
 
     /// Setup the update requester thread which will trigger an update every 
@@ -42,19 +1002,111 @@ impl State for GuiState {
 
         self.updater_thread = Some(thread::spawn(move || {
             loop {
-                sender.send(ShellRequest::Update).unwrap();
+                // The window has closed if nothing is listening any more
+                if sender.send(ShellRequest::Update).is_err() {
+                    break;
+                }
                 thread::sleep(Duration::from_millis(33));
 
-                // Check for exit request
-                // TODO: Find some way of making this true...
+                // Check for exit request, set when the state is dropped
                 if *exit_thread.lock().unwrap() {
                     break;
                 }
             }
         }));
+
+        self.start_link();
+        self.load_terrain();
     }
 
     fn update(&mut self, _: &mut Registry, ctx: &mut Context<'_>) {
+        self.process_tms();
+
+        while let Some(action) = self.actions.pop_front() {
+            match action {
+                Action::Abort => self.abort(),
+                Action::Send => {
+                    let num_params = self.tc_registry
+                        .types()[self.selected_tc]
+                        .params.len()
+                        .min(MAX_TC_PARAMS);
+                    let values: Vec<String> = (0..num_params)
+                        .map(|i| ctx.widget()
+                            .get::<String16>(&format!("param_value_{}", i))
+                            .as_string())
+                        .collect();
+                    self.send_form(&values);
+                },
+                Action::PrevTcType => self.select_tc_type(-1),
+                Action::NextTcType => self.select_tc_type(1),
+                Action::SelectHistory(row) => self.select_history_row(row),
+                Action::ScrollHistory(step) => self.scroll_history(step),
+                Action::ResendTc => self.resend_tc(),
+                Action::EditTc => self.edit_tc(),
+                Action::RunSequence => {
+                    let path = ctx.widget()
+                        .get::<String16>("sequence_path")
+                        .as_string();
+                    self.run_sequence(path.trim());
+                },
+                Action::PauseSequence => {
+                    if let Some(r) = self.sequence_runner.as_mut() {
+                        r.pause();
+                    }
+                },
+                Action::ResumeSequence => {
+                    if let Some(r) = self.sequence_runner.as_mut() {
+                        r.resume();
+                    }
+                },
+                Action::StopSequence => {
+                    if let Some(r) = self.sequence_runner.as_mut() {
+                        r.abort();
+                    }
+                },
+                Action::CameraView(mode) => self.view_input.mode = Some(mode),
+                Action::TrailColour => self.toggle_trail_colour(),
+                Action::TrailDecay => self.next_trail_decay(),
+                Action::ClearTrail => self.clear_trail = true,
+                Action::PlanPath => {
+                    self.view_input.planning = !self.view_input.planning
+                },
+                Action::SendPath => self.send_path(),
+                Action::ClearPath => self.path_plan.clear()
+            }
+        }
+
+        if self.tc_form_changed {
+            self.update_tc_form(ctx);
+        }
+
+        self.update_history(ctx);
+
+        let (send_text, run_text) = self.update_armed();
+        ctx.widget().set("send_text", String16::from(send_text));
+        ctx.widget().set("run_text", String16::from(run_text));
+
+        let sequence_text = self.update_sequence();
+        ctx.widget().set("sequence_text", String16::from(sequence_text));
+
+        self.update_tm_panel(ctx);
+
+        for row in 0..STATUS_ROWS {
+            ctx.widget().set(
+                &format!("status_row_{}", row), 
+                String16::from(self.status.row(row)));
+        }
+
+        ctx.widget().set("plan_text", String16::from(
+            match self.view_input.planning {
+                true => "Stop planning",
+                false => "Plan path"
+            }));
+
+        let (trail_colour_text, trail_decay_text) = self.trail_texts();
+        ctx.widget().set("trail_colour_text", String16::from(trail_colour_text));
+        ctx.widget().set("trail_decay_text", String16::from(trail_decay_text));
+
         self.current_time_utc = Utc::now();
         self.frame_counter += 1;
 
@@ -67,6 +1119,48 @@ impl State for GuiState {
             .downcast_ref::<VirtSpacePipeline>() {
             
             virt_space.frame_counter.set(self.frame_counter);
+
+            let mut rover_pose = virt_space.rover_pose.get();
+            let mut rover_trail = virt_space.rover_trail.borrow_mut();
+
+            if self.clear_trail {
+                rover_trail.clear();
+                self.clear_trail = false;
+            }
+            rover_trail.config = self.trail_config;
+
+            for p in self.new_rover_poses.drain(..) {
+                let pose = RoverPose::from(&p);
+                rover_pose.push(pose, p.timestamp_utc);
+                rover_trail.push(&pose, p.timestamp_utc);
+            }
+            rover_trail.prune(Instant::now());
+            virt_space.rover_pose.set(rover_pose);
+
+            if let Some(t) = self.new_terrain.take() {
+                *virt_space.terrain.borrow_mut() = Some(t);
+            }
+            let terrain = virt_space.terrain.borrow();
+
+            let mut camera = virt_space.camera.get();
+            self.view_input.apply(
+                &mut camera, &mut self.path_plan,
+                rover_pose.current_pose(Instant::now()), 
+                virt_space.view_size.get(),
+                terrain.as_ref().map(|t| &t.dem));
+            virt_space.camera.set(camera);
+
+            let mut planned_path = virt_space.planned_path.borrow_mut();
+            if planned_path.as_slice() != self.path_plan.waypoints() {
+                *planned_path = self.path_plan.waypoints().to_vec();
+
+                // The armed path is the one from before it was changed, so
+                // don't let it be sent
+                if self.is_path_armed() {
+                    self.status.info("Planned path changed, disarmed it");
+                    self.armed_tc = None;
+                }
+            }
         }
 
         // Update time value
@@ -75,4 +1169,22 @@ impl State for GuiState {
             self.current_time_utc.format("%Y-%m-%d %H:%M:%S").to_string(),
             self.frame_counter)));
     }
-}
\ No newline at end of file
+}
+
+impl Drop for GuiState {
+
+    /// Stop the updater thread and the link to the rover when the GUI closes,
+    /// so that the rover is told the GUI has disconnected.
+    fn drop(&mut self) {
+        *self.exit_updater.lock().unwrap() = true;
+
+        // The window's gone so there's nowhere else to report this
+        if let Some(report) = self.stop_link() {
+            println!("{}", report);
+        }
+
+        if let Some(thread) = self.updater_thread.take() {
+            thread.join().ok();
+        }
+    }
+}
@@ -0,0 +1,14 @@
+// ---------------------------------------------------------------------------
+// AUTONOMY CONTROL
+//
+// The TM/TC protocol used to talk to the rover's AutonomyManager, shared by
+// the AutonomyControl GUI and the `rover_sim` mock rover.
+// ---------------------------------------------------------------------------
+
+pub mod tm_tc_interface;
+pub mod tm_tc_framing;
+pub mod tm_tc_error;
+pub mod tm_tc_types;
+pub mod tc_constructor;
+pub mod tc_sequence;
+pub mod tm_deconstructor;
@@ -9,34 +9,461 @@
 
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc};
-use crate::tm_tc_interface::{TmTcIf, TmTcData};
+use std::fmt;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use crate::tm_tc_interface::{
+    TmTcIf, TmTcData, TcHandle, TcExecTime, StopReport};
+use crate::tm_tc_error::TmTcError;
+use crate::tm_tc_types::{
+    RoverMode, ParamKind, ParamSchema, ROVER_MODE_NAMES, check_param};
+use crate::tc_sequence::TcSequence;
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+// Limits on locomotion TC parameters, set by what the rover's mobility system
+// can safely do
+pub const MAX_DRIVE_DISTANCE_M: f64 = 10.0;
+pub const MIN_DRIVE_SPEED_MPS: f64 = 0.01;
+pub const MAX_DRIVE_SPEED_MPS: f64 = 0.5;
+pub const MAX_CURVATURE_PM: f64 = 2.0;
+pub const MAX_TURN_ANGLE_RAD: f64 = 2.0 * std::f64::consts::PI;
+pub const MIN_TURN_RATE_RADPS: f64 = 0.01;
+pub const MAX_TURN_RATE_RADPS: f64 = 0.5;
+
+// Limits on waypoint navigation TCs
+pub const MAX_WAYPOINTS: usize = 256;
+pub const MAX_WAYPOINT_RANGE_M: f64 = 1000.0;
+pub const MIN_WAYPOINT_TOLERANCE_M: f64 = 0.05;
+pub const MAX_WAYPOINT_TOLERANCE_M: f64 = 5.0;
+
+// How long the operator has to confirm an armed TC before it must be armed
+// again
+pub const ARM_TIMEOUT_S: u64 = 10;
 
 // ---------------------------------------------------------------------------
 // TC CONSTRUCTOR
 // ---------------------------------------------------------------------------
 
-pub struct TcConstructor<'a> {
+// Owns the interface so that it can keep a history of every TC sent through
+// it, and so can be held by the GUI for the whole session.
+pub struct TcConstructor {
 
-    tm_tc_if: &'a mut TmTcIf,
+    tm_tc_if: TmTcIf,
+
+    // Every TC sent through the constructor, oldest first
+    history: Vec<SentTc>
 
 }
 
-impl<'a> TcConstructor<'a> {
+impl TcConstructor {
 
     // Instantiate a new `TcConstructor` - used to build and send TCs to the
     // TmTcIf.
-    pub fn new(tm_tc_if: &'a mut TmTcIf) -> Self {
+    pub fn new(tm_tc_if: TmTcIf) -> Self {
         TcConstructor {
-            tm_tc_if: tm_tc_if
+            tm_tc_if,
+            history: vec![]
         }
     }
 
+    // Get the interface, for example to read TMs from it
+    pub fn tm_tc_if(&self) -> &TmTcIf {
+        &self.tm_tc_if
+    }
+
+    // Stop the interface, see `TmTcIf::stop`
+    pub fn stop(self) -> Result<StopReport, TmTcError> {
+        self.tm_tc_if.stop()
+    }
+
+    // Get every TC sent through the constructor this session, oldest first
+    pub fn history(&self) -> &[SentTc] {
+        &self.history
+    }
+
     // Take a piece of TC data, generally a `TcXxx` object, and send it to the
-    // interface. 
-    pub fn build_and_send<T>(&mut self, data: T) -> Result<(), String> where
+    // interface. The returned handle can be polled or waited on to find out 
+    // whether the rover accepted and executed the TC.
+    //
+    // If the rover has reported its mode the TC is checked against it first,
+    // and rejected with a `ModeViolation` if it's not allowed. If the mode
+    // isn't known the TC is sent anyway and it's left to the rover to refuse
+    // it.
+    //
+    // Critical TCs are refused with a `NotConfirmed` error, they must be sent
+    // with `arm` and `confirm` instead.
+    pub fn build_and_send<T>(&mut self, data: T) -> Result<TcHandle, TmTcError> where
         T: TmTcData {
         
-        self.tm_tc_if.add_pending_tc(data)
+        require_not_critical(&data)?;
+        self.send_checked(data)
+    }
+
+    // Send a piece of TC data to be held in the rover's schedule and executed
+    // at `exec_time`. The rover's current mode isn't checked since it may
+    // well have changed by the time the TC is executed, the rover will fail
+    // the TC then if it's not allowed. Critical TCs are refused, as with
+    // `build_and_send`, and must be sent with `arm_scheduled` and `confirm`.
+    pub fn build_and_schedule<T>(&mut self, data: T, exec_time: TcExecTime) 
+        -> Result<TcHandle, TmTcError> where T: TmTcData {
+
+        require_not_critical(&data)?;
+        self.schedule_checked(data, exec_time)
+    }
+
+    // Arm a piece of TC data ready to be sent by `confirm`. The TC is checked
+    // now, so the operator isn't asked to confirm something that's going to
+    // be refused, and again when it's confirmed. Any TC can be armed, but
+    // only critical ones need to be.
+    pub fn arm<T>(&self, data: T) -> Result<ArmedTc, TmTcError> where
+        T: TmTcData + 'static {
+
+        data.validate()?;
+
+        if let Some(mode) = self.tm_tc_if.rover_mode() {
+            data.check_mode(mode)?;
+        }
+
+        Ok(ArmedTc::new(T::TYPE_ID, Box::new(move |c| c.send_checked(data))))
+    }
+
+    // Arm a piece of TC data ready to be scheduled by `confirm`, see
+    // `build_and_schedule`.
+    pub fn arm_scheduled<T>(&self, data: T, exec_time: TcExecTime) 
+        -> Result<ArmedTc, TmTcError> where T: TmTcData + 'static {
+
+        data.validate()?;
+
+        Ok(ArmedTc::new(T::TYPE_ID, Box::new(move |c| {
+            c.schedule_checked(data, exec_time)
+        })))
+    }
+
+    // Send a TC which has been armed. If it was armed more than 
+    // `ARM_TIMEOUT_S` ago it's refused with a `NotConfirmed` error and must
+    // be armed again.
+    pub fn confirm(&mut self, armed: ArmedTc) -> Result<TcHandle, TmTcError> {
+        if armed.is_expired() {
+            return Err(TmTcError::NotConfirmed(format!(
+                "{} was armed more than {} s ago, arm it again",
+                armed.type_id, ARM_TIMEOUT_S)))
+        }
+
+        (armed.send)(self)
+    }
+
+    // Check a TC against the rover's mode, if it's known, and send it
+    fn send_checked<T>(&mut self, data: T) -> Result<TcHandle, TmTcError> where
+        T: TmTcData {
+
+        if let Some(mode) = self.tm_tc_if.rover_mode() {
+            data.check_mode(mode)?;
+        }
+
+        let params = serde_json::to_value(&data)?;
+        let handle = self.tm_tc_if.add_pending_tc(data)?;
+        self.record(T::TYPE_ID, params, &handle, None);
+
+        Ok(handle)
+    }
+
+    // Send a TC to the rover's schedule
+    fn schedule_checked<T>(&mut self, data: T, exec_time: TcExecTime) 
+        -> Result<TcHandle, TmTcError> where T: TmTcData {
+
+        let params = serde_json::to_value(&data)?;
+        let handle = self.tm_tc_if.add_scheduled_tc(data, exec_time.clone())?;
+        self.record(T::TYPE_ID, params, &handle, Some(exec_time));
+
+        Ok(handle)
+    }
+
+    // Add a TC which has just been given to the interface to the history
+    fn record(
+        &mut self,
+        type_id: &'static str,
+        params: serde_json::Value,
+        handle: &TcHandle,
+        exec_time: Option<TcExecTime>
+    ) {
+        self.history.push(SentTc {
+            type_id,
+            params,
+            handle: handle.clone(),
+            exec_time
+        });
+    }
+
+    // Load a sequence of TCs from a JSON file, checking every step against
+    // the known TC types. Run it with a `TcSequenceRunner`.
+    pub fn load_sequence<P>(path: P) -> Result<TcSequence, TmTcError> where
+        P: AsRef<Path> {
+
+        TcSequence::from_file(path, &TcRegistry::new())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// SENT TC
+// ---------------------------------------------------------------------------
+
+// A TC sent through the `TcConstructor`, kept in its history
+#[derive(Clone)]
+pub struct SentTc {
+    pub type_id: &'static str,
+
+    // The TC's data as sent, which can be given to the TC's type in the 
+    // `TcRegistry` to send it again
+    pub params: serde_json::Value,
+
+    // Handle for following the TC's progress, which also holds the frame
+    // counter and time it was sent with
+    pub handle: TcHandle,
+
+    // When the TC was scheduled to execute, or None if it was executed
+    // straight away
+    pub exec_time: Option<TcExecTime>
+}
+
+impl SentTc {
+
+    // Summarise the TC's parameters in a single line, for example 
+    // "distance_m=1.0, speed_mps=0.2". Lists are shown by their length only.
+    pub fn params_summary(&self) -> String {
+        let params = match self.params.as_object() {
+            Some(p) => p,
+            None => return String::new()
+        };
+
+        params.iter()
+            .map(|(name, value)| match value {
+                serde_json::Value::Array(a) => format!(
+                    "{}=[{} items]", name, a.len()),
+                serde_json::Value::String(s) => format!("{}={}", name, s),
+                v => format!("{}={}", name, v)
+            })
+            .collect::<Vec<String>>()
+            .join(", ")
+    }
+}
+
+// Shows the TC as a line of the TC history, for example
+// "#12 14:02:33 TcDriveStraight distance_m=1.0, speed_mps=0.2 - Completed"
+impl fmt::Display for SentTc {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.handle.frame_counter() {
+            Some(fc) => write!(f, "#{}", fc)?,
+            None => write!(f, "#-")?
+        }
+
+        match self.handle.send_time_utc() {
+            Some(t) => write!(f, " {}", t.format("%H:%M:%S"))?,
+            None => write!(f, " --:--:--")?
+        }
+
+        write!(f, " {}", self.type_id)?;
+
+        let summary = self.params_summary();
+        if !summary.is_empty() {
+            write!(f, " {}", summary)?;
+        }
+
+        if self.exec_time.is_some() {
+            write!(f, " (scheduled)")?;
+        }
+
+        write!(f, " - {}", self.handle.status())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ARMED TC
+// ---------------------------------------------------------------------------
+
+// Sends an armed TC once it's confirmed
+type ArmedSendFn = Box<dyn FnOnce(&mut TcConstructor) 
+    -> Result<TcHandle, TmTcError>>;
+
+// A TC which has been armed by `TcConstructor::arm` and is waiting for the
+// operator to confirm it. Dropping it disarms the TC without sending it.
+pub struct ArmedTc {
+    type_id: &'static str,
+    armed_time: Instant,
+    send: ArmedSendFn
+}
+
+impl ArmedTc {
+
+    fn new(type_id: &'static str, send: ArmedSendFn) -> Self {
+        ArmedTc {
+            type_id,
+            armed_time: Instant::now(),
+            send
+        }
+    }
+
+    pub fn type_id(&self) -> &'static str {
+        self.type_id
+    }
+
+    // How long is left to confirm the TC, zero once it's expired
+    pub fn time_left(&self) -> Duration {
+        Duration::from_secs(ARM_TIMEOUT_S)
+            .checked_sub(self.armed_time.elapsed())
+            .unwrap_or_default()
+    }
+
+    // Returns true if it's too late to confirm the TC
+    pub fn is_expired(&self) -> bool {
+        self.time_left() == Duration::from_secs(0)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TC REGISTRY
+// ---------------------------------------------------------------------------
+
+// Checks TC parameters given as JSON without sending anything
+type TcCheckFn = fn(&serde_json::Value) -> Result<(), TmTcError>;
+
+// Parses TC parameters given as JSON and says whether the TC is critical
+type TcCriticalFn = fn(&serde_json::Value) -> Result<bool, TmTcError>;
+
+// Parses TC parameters given as JSON and sends the TC
+type TcSendFn = fn(&mut TcConstructor, serde_json::Value) 
+    -> Result<TcHandle, TmTcError>;
+
+// Parses TC parameters given as JSON and arms the TC
+type TcArmFn = fn(&TcConstructor, serde_json::Value) 
+    -> Result<ArmedTc, TmTcError>;
+
+// Everything needed to deal with a TC whose type is only known by its type
+// id, for example one read from a sequence file.
+#[derive(Clone, Copy)]
+pub struct TcTypeInfo {
+    pub type_id: &'static str,
+    pub params: &'static [ParamSchema],
+    check: TcCheckFn,
+    critical: TcCriticalFn,
+    send: TcSendFn,
+    arm: TcArmFn
+}
+
+impl TcTypeInfo {
+
+    fn of<T>() -> Self where T: TmTcData + 'static {
+        TcTypeInfo {
+            type_id: T::TYPE_ID,
+            params: T::PARAMS,
+            check: check_params::<T>,
+            critical: critical_params::<T>,
+            send: send_params::<T>,
+            arm: arm_params::<T>
+        }
+    }
+
+    // Build the JSON parameters for a TC of this type from the text entered
+    // for each parameter, given in the same order as `params`.
+    pub fn params_from_text<S>(&self, values: &[S]) 
+        -> Result<serde_json::Value, TmTcError> where S: AsRef<str> {
+
+        if values.len() != self.params.len() {
+            return Err(TmTcError::InvalidParameter(format!(
+                "{} takes {} parameters but {} were given",
+                self.type_id, self.params.len(), values.len())))
+        }
+
+        let mut params = serde_json::Map::new();
+
+        for (schema, value) in self.params.iter().zip(values) {
+            params.insert(
+                String::from(schema.name), schema.parse(value.as_ref())?);
+        }
+
+        Ok(serde_json::Value::Object(params))
+    }
+
+    // Check that `params` describe a valid TC of this type
+    pub fn check(&self, params: &serde_json::Value) -> Result<(), TmTcError> {
+        (self.check)(params)
+    }
+
+    // Check whether a TC of this type with `params` is critical, and so has
+    // to be armed and confirmed rather than sent
+    pub fn is_critical(&self, params: &serde_json::Value) 
+        -> Result<bool, TmTcError> {
+
+        (self.critical)(params)
+    }
+
+    // Build a TC of this type from `params` and send it
+    pub fn send(&self, tc_cstr: &mut TcConstructor, params: serde_json::Value) 
+        -> Result<TcHandle, TmTcError> {
+
+        (self.send)(tc_cstr, params)
+    }
+
+    // Build a TC of this type from `params` and arm it
+    pub fn arm(&self, tc_cstr: &TcConstructor, params: serde_json::Value) 
+        -> Result<ArmedTc, TmTcError> {
+
+        (self.arm)(tc_cstr, params)
+    }
+}
+
+// The TC types an operator can send by type id. Heartbeats are sent by the
+// TmTcIf itself so aren't included.
+pub struct TcRegistry {
+    types: Vec<TcTypeInfo>
+}
+
+impl TcRegistry {
+
+    pub fn new() -> Self {
+        let mut registry = TcRegistry {
+            types: vec![]
+        };
+
+        registry.register::<TcAbort>();
+        registry.register::<TcSetMode>();
+        registry.register::<TcDriveStraight>();
+        registry.register::<TcDriveArc>();
+        registry.register::<TcPointTurn>();
+        registry.register::<TcNavigateWaypoints>();
+        registry.register::<TcTraversePause>();
+        registry.register::<TcTraverseResume>();
+        registry.register::<TcTraverseCancel>();
+        registry.register::<TcScheduleList>();
+        registry.register::<TcScheduleDelete>();
+        registry.register::<TcScheduleClear>();
+        registry.register::<TcDisconnect>();
+
+        registry
+    }
+
+    fn register<T>(&mut self) where T: TmTcData + 'static {
+        self.types.push(TcTypeInfo::of::<T>());
+    }
+
+    // Get the information on a TC type, or an error if it isn't known
+    pub fn get(&self, type_id: &str) -> Result<&TcTypeInfo, TmTcError> {
+        self.types.iter()
+            .find(|t| t.type_id == type_id)
+            .ok_or_else(|| TmTcError::UnknownTypeId(String::from(type_id)))
+    }
+
+    // Get every known TC type, in the order they were registered
+    pub fn types(&self) -> &[TcTypeInfo] {
+        &self.types
+    }
+}
+
+impl Default for TcRegistry {
+    fn default() -> Self {
+        TcRegistry::new()
     }
 }
 
@@ -62,14 +489,14 @@ impl TcHeartbeat {
 }
 
 impl TmTcData for TcHeartbeat {
-    fn type_id(&self) -> String {
-        String::from("TcHeartbeat")
-    }
+    const TYPE_ID: &'static str = "TcHeartbeat";
 }
 
 // DISCONNECT
 //
-// Instructs the rover to disconnect from the control GUI
+// Instructs the rover to disconnect from the control GUI. Critical when sent
+// by the operator, the TmTcIf sends its own when it's stopped without going
+// through the `TcConstructor`.
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct TcDisconnect {}
@@ -81,7 +508,665 @@ impl TcDisconnect {
 }
 
 impl TmTcData for TcDisconnect {
-    fn type_id(&self) -> String {
-        String::from("TcDisconnect")
+    const TYPE_ID: &'static str = "TcDisconnect";
+    const CRITICAL: bool = true;
+}
+
+// ABORT
+//
+// Immediately stop whatever the rover is doing and bring it to a safe stop.
+// Sent ahead of any other queued TCs, which are discarded, and resent until
+// the rover acknowledges it.
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TcAbort {}
+
+impl TcAbort {
+    pub fn new() -> Self {
+        TcAbort {}
+    }
+}
+
+impl TmTcData for TcAbort {
+    const TYPE_ID: &'static str = "TcAbort";
+    const PRIORITY: bool = true;
+}
+
+// DRIVE STRAIGHT
+//
+// Drive forwards or backwards in a straight line. A negative distance drives
+// the rover in reverse.
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TcDriveStraight {
+    pub distance_m: f64,
+    pub speed_mps: f64
+}
+
+impl TcDriveStraight {
+    pub fn new(distance_m: f64, speed_mps: f64) -> Result<Self, TmTcError> {
+        let tc = TcDriveStraight {
+            distance_m,
+            speed_mps
+        };
+        tc.validate()?;
+        Ok(tc)
+    }
+}
+
+impl TmTcData for TcDriveStraight {
+    const TYPE_ID: &'static str = "TcDriveStraight";
+    const CRITICAL: bool = true;
+    const PARAMS: &'static [ParamSchema] = &[
+        ParamSchema {
+            name: "distance_m",
+            units: "m",
+            kind: ParamKind::Number {
+                min: -MAX_DRIVE_DISTANCE_M, max: MAX_DRIVE_DISTANCE_M },
+            default: "1.0"
+        },
+        ParamSchema {
+            name: "speed_mps",
+            units: "m/s",
+            kind: ParamKind::Number {
+                min: MIN_DRIVE_SPEED_MPS, max: MAX_DRIVE_SPEED_MPS },
+            default: "0.2"
+        }
+    ];
+
+    fn check_mode(&self, mode: RoverMode) -> Result<(), TmTcError> {
+        require_mode(Self::TYPE_ID, mode, RoverMode::Manual)
+    }
+
+    fn validate(&self) -> Result<(), TmTcError> {
+        check_param("distance_m", self.distance_m,
+            -MAX_DRIVE_DISTANCE_M, MAX_DRIVE_DISTANCE_M)?;
+        check_param("speed_mps", self.speed_mps,
+            MIN_DRIVE_SPEED_MPS, MAX_DRIVE_SPEED_MPS)
+    }
+}
+
+// DRIVE ARC
+//
+// Drive along an arc using Ackermann steering. Curvature is the inverse of
+// the turn radius, positive to turn left and zero to drive straight. The
+// distance is measured along the arc, and a negative distance drives the
+// rover in reverse.
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TcDriveArc {
+    pub curvature_pm: f64,
+    pub distance_m: f64,
+    pub speed_mps: f64
+}
+
+impl TcDriveArc {
+    pub fn new(curvature_pm: f64, distance_m: f64, speed_mps: f64) 
+        -> Result<Self, TmTcError> {
+
+        let tc = TcDriveArc {
+            curvature_pm,
+            distance_m,
+            speed_mps
+        };
+        tc.validate()?;
+        Ok(tc)
+    }
+}
+
+impl TmTcData for TcDriveArc {
+    const TYPE_ID: &'static str = "TcDriveArc";
+    const CRITICAL: bool = true;
+    const PARAMS: &'static [ParamSchema] = &[
+        ParamSchema {
+            name: "curvature_pm",
+            units: "1/m",
+            kind: ParamKind::Number {
+                min: -MAX_CURVATURE_PM, max: MAX_CURVATURE_PM },
+            default: "0.5"
+        },
+        ParamSchema {
+            name: "distance_m",
+            units: "m",
+            kind: ParamKind::Number {
+                min: -MAX_DRIVE_DISTANCE_M, max: MAX_DRIVE_DISTANCE_M },
+            default: "1.0"
+        },
+        ParamSchema {
+            name: "speed_mps",
+            units: "m/s",
+            kind: ParamKind::Number {
+                min: MIN_DRIVE_SPEED_MPS, max: MAX_DRIVE_SPEED_MPS },
+            default: "0.2"
+        }
+    ];
+
+    fn check_mode(&self, mode: RoverMode) -> Result<(), TmTcError> {
+        require_mode(Self::TYPE_ID, mode, RoverMode::Manual)
+    }
+
+    fn validate(&self) -> Result<(), TmTcError> {
+        check_param("curvature_pm", self.curvature_pm,
+            -MAX_CURVATURE_PM, MAX_CURVATURE_PM)?;
+        check_param("distance_m", self.distance_m,
+            -MAX_DRIVE_DISTANCE_M, MAX_DRIVE_DISTANCE_M)?;
+        check_param("speed_mps", self.speed_mps,
+            MIN_DRIVE_SPEED_MPS, MAX_DRIVE_SPEED_MPS)
+    }
+}
+
+// POINT TURN
+//
+// Turn on the spot. A positive angle turns the rover left (anticlockwise
+// when seen from above).
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TcPointTurn {
+    pub angle_rad: f64,
+    pub rate_radps: f64
+}
+
+impl TcPointTurn {
+    pub fn new(angle_rad: f64, rate_radps: f64) -> Result<Self, TmTcError> {
+        let tc = TcPointTurn {
+            angle_rad,
+            rate_radps
+        };
+        tc.validate()?;
+        Ok(tc)
+    }
+}
+
+impl TmTcData for TcPointTurn {
+    const TYPE_ID: &'static str = "TcPointTurn";
+    const CRITICAL: bool = true;
+    const PARAMS: &'static [ParamSchema] = &[
+        ParamSchema {
+            name: "angle_rad",
+            units: "rad",
+            kind: ParamKind::Number {
+                min: -MAX_TURN_ANGLE_RAD, max: MAX_TURN_ANGLE_RAD },
+            default: "1.5708"
+        },
+        ParamSchema {
+            name: "rate_radps",
+            units: "rad/s",
+            kind: ParamKind::Number {
+                min: MIN_TURN_RATE_RADPS, max: MAX_TURN_RATE_RADPS },
+            default: "0.2"
+        }
+    ];
+
+    fn check_mode(&self, mode: RoverMode) -> Result<(), TmTcError> {
+        require_mode(Self::TYPE_ID, mode, RoverMode::Manual)
+    }
+
+    fn validate(&self) -> Result<(), TmTcError> {
+        check_param("angle_rad", self.angle_rad,
+            -MAX_TURN_ANGLE_RAD, MAX_TURN_ANGLE_RAD)?;
+        check_param("rate_radps", self.rate_radps,
+            MIN_TURN_RATE_RADPS, MAX_TURN_RATE_RADPS)
+    }
+}
+
+// NAVIGATE WAYPOINTS
+//
+// Follow a path through a list of waypoints, using the rover's own path
+// following. Each waypoint may give the heading the rover should face on
+// arrival, and how close the rover needs to get to it, otherwise the rover's
+// defaults are used.
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Waypoint {
+    pub x_m: f64,
+    pub y_m: f64,
+    pub heading_rad: Option<f64>,
+    pub tolerance_m: Option<f64>
+}
+
+impl Waypoint {
+    pub fn new(x_m: f64, y_m: f64) -> Self {
+        Waypoint {
+            x_m,
+            y_m,
+            heading_rad: None,
+            tolerance_m: None
+        }
+    }
+
+    pub fn with_heading(mut self, heading_rad: f64) -> Self {
+        self.heading_rad = Some(heading_rad);
+        self
+    }
+
+    pub fn with_tolerance(mut self, tolerance_m: f64) -> Self {
+        self.tolerance_m = Some(tolerance_m);
+        self
+    }
+
+    // Check the waypoint's parameters, `index` is the waypoint's position in
+    // the list and is used in any error.
+    fn validate(&self, index: usize) -> Result<(), TmTcError> {
+        check_param(&format!("waypoints[{}].x_m", index), self.x_m,
+            -MAX_WAYPOINT_RANGE_M, MAX_WAYPOINT_RANGE_M)?;
+        check_param(&format!("waypoints[{}].y_m", index), self.y_m,
+            -MAX_WAYPOINT_RANGE_M, MAX_WAYPOINT_RANGE_M)?;
+
+        if let Some(h) = self.heading_rad {
+            check_param(&format!("waypoints[{}].heading_rad", index), h,
+                -std::f64::consts::PI, std::f64::consts::PI)?;
+        }
+
+        if let Some(t) = self.tolerance_m {
+            check_param(&format!("waypoints[{}].tolerance_m", index), t,
+                MIN_WAYPOINT_TOLERANCE_M, MAX_WAYPOINT_TOLERANCE_M)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TcNavigateWaypoints {
+    pub waypoints: Vec<Waypoint>
+}
+
+impl TcNavigateWaypoints {
+    pub fn new(waypoints: Vec<Waypoint>) -> Result<Self, TmTcError> {
+        let tc = TcNavigateWaypoints {
+            waypoints
+        };
+        tc.validate()?;
+        Ok(tc)
+    }
+}
+
+impl TmTcData for TcNavigateWaypoints {
+    const TYPE_ID: &'static str = "TcNavigateWaypoints";
+    const CRITICAL: bool = true;
+    const PARAMS: &'static [ParamSchema] = &[
+        ParamSchema {
+            name: "waypoints",
+            units: "",
+            kind: ParamKind::Json,
+            default: "[{\"x_m\": 1.0, \"y_m\": 0.0}]"
+        }
+    ];
+
+    fn check_mode(&self, mode: RoverMode) -> Result<(), TmTcError> {
+        require_mode(Self::TYPE_ID, mode, RoverMode::Autonomous)
+    }
+
+    fn validate(&self) -> Result<(), TmTcError> {
+        if self.waypoints.is_empty() || self.waypoints.len() > MAX_WAYPOINTS {
+            return Err(TmTcError::InvalidParameter(format!(
+                "Between 1 and {} waypoints are needed but {} were given",
+                MAX_WAYPOINTS, self.waypoints.len())))
+        }
+
+        for (i, w) in self.waypoints.iter().enumerate() {
+            w.validate(i)?;
+        }
+
+        Ok(())
+    }
+}
+
+// TRAVERSE PAUSE
+//
+// Stop the rover where it is, keeping the rest of the current traverse so it
+// can be resumed
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TcTraversePause {}
+
+impl TcTraversePause {
+    pub fn new() -> Self {
+        TcTraversePause {}
+    }
+}
+
+impl TmTcData for TcTraversePause {
+    const TYPE_ID: &'static str = "TcTraversePause";
+
+    fn check_mode(&self, mode: RoverMode) -> Result<(), TmTcError> {
+        require_mode(Self::TYPE_ID, mode, RoverMode::Autonomous)
+    }
+}
+
+// TRAVERSE RESUME
+//
+// Carry on with a paused traverse
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TcTraverseResume {}
+
+impl TcTraverseResume {
+    pub fn new() -> Self {
+        TcTraverseResume {}
+    }
+}
+
+impl TmTcData for TcTraverseResume {
+    const TYPE_ID: &'static str = "TcTraverseResume";
+    const CRITICAL: bool = true;
+
+    fn check_mode(&self, mode: RoverMode) -> Result<(), TmTcError> {
+        require_mode(Self::TYPE_ID, mode, RoverMode::Autonomous)
+    }
+}
+
+// TRAVERSE CANCEL
+//
+// Stop the rover and throw away the rest of the current traverse
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TcTraverseCancel {}
+
+impl TcTraverseCancel {
+    pub fn new() -> Self {
+        TcTraverseCancel {}
+    }
+}
+
+impl TmTcData for TcTraverseCancel {
+    const TYPE_ID: &'static str = "TcTraverseCancel";
+
+    fn check_mode(&self, mode: RoverMode) -> Result<(), TmTcError> {
+        require_mode(Self::TYPE_ID, mode, RoverMode::Autonomous)
+    }
+}
+
+// SET MODE
+//
+// Switch the rover into a new mode. Only some changes are allowed, see
+// `RoverMode::can_change_to`.
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TcSetMode {
+    pub mode: RoverMode
+}
+
+impl TcSetMode {
+    pub fn new(mode: RoverMode) -> Self {
+        TcSetMode {
+            mode
+        }
+    }
+}
+
+impl TmTcData for TcSetMode {
+    const TYPE_ID: &'static str = "TcSetMode";
+    const PARAMS: &'static [ParamSchema] = &[
+        ParamSchema {
+            name: "mode",
+            units: "",
+            kind: ParamKind::Choice(ROVER_MODE_NAMES),
+            default: "Standby"
+        }
+    ];
+
+    // Handing control of the rover to its own navigation needs confirming,
+    // the other modes only make the rover less likely to move
+    fn is_critical(&self) -> bool {
+        self.mode == RoverMode::Autonomous
+    }
+
+    fn check_mode(&self, mode: RoverMode) -> Result<(), TmTcError> {
+        if mode.can_change_to(self.mode) {
+            Ok(())
+        }
+        else {
+            Err(TmTcError::ModeViolation(format!(
+                "Cannot change from {} to {} mode", mode, self.mode)))
+        }
+    }
+}
+
+// SCHEDULE LIST
+//
+// Ask the rover for the contents of its schedule, which it sends back as a
+// `TmSchedule`
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TcScheduleList {}
+
+impl TcScheduleList {
+    pub fn new() -> Self {
+        TcScheduleList {}
+    }
+}
+
+impl TmTcData for TcScheduleList {
+    const TYPE_ID: &'static str = "TcScheduleList";
+}
+
+// SCHEDULE DELETE
+//
+// Remove a single TC from the rover's schedule, identified by the frame
+// counter it was sent with
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TcScheduleDelete {
+    pub frame_counter: u32
+}
+
+impl TcScheduleDelete {
+    pub fn new(frame_counter: u32) -> Self {
+        TcScheduleDelete {
+            frame_counter
+        }
+    }
+
+    // Delete the scheduled TC behind `handle`, which must have been sent
+    pub fn for_tc(handle: &TcHandle) -> Result<Self, TmTcError> {
+        match handle.frame_counter() {
+            Some(f) => Ok(TcScheduleDelete::new(f)),
+            None => Err(TmTcError::InvalidParameter(format!(
+                "The {} TC hasn't been sent so can't be deleted from the \
+                schedule", handle.data_type_id())))
+        }
+    }
+}
+
+impl TmTcData for TcScheduleDelete {
+    const TYPE_ID: &'static str = "TcScheduleDelete";
+    const PARAMS: &'static [ParamSchema] = &[
+        ParamSchema {
+            name: "frame_counter",
+            units: "",
+            kind: ParamKind::Count,
+            default: "0"
+        }
+    ];
+}
+
+// SCHEDULE CLEAR
+//
+// Remove every TC from the rover's schedule
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct TcScheduleClear {}
+
+impl TcScheduleClear {
+    pub fn new() -> Self {
+        TcScheduleClear {}
+    }
+}
+
+impl TmTcData for TcScheduleClear {
+    const TYPE_ID: &'static str = "TcScheduleClear";
+}
+
+// ---------------------------------------------------------------------------
+// PRIVATE FUNCTIONS
+// ---------------------------------------------------------------------------
+
+// Check that the rover is in the one mode a TC can be executed in
+fn require_mode(type_id: &str, mode: RoverMode, required: RoverMode) 
+    -> Result<(), TmTcError> {
+
+    if mode == required {
+        Ok(())
+    }
+    else {
+        Err(TmTcError::ModeViolation(format!(
+            "{} needs {} mode but the rover is in {} mode", 
+            type_id, required, mode)))
+    }
+}
+
+// Parse the parameters of a `T` from JSON and check them
+fn check_params<T>(params: &serde_json::Value) -> Result<(), TmTcError> where
+    T: TmTcData {
+
+    let data: T = serde_json::from_value(params.clone())?;
+    data.validate()
+}
+
+// Parse the parameters of a `T` from JSON and send it
+fn send_params<T>(tc_cstr: &mut TcConstructor, params: serde_json::Value) 
+    -> Result<TcHandle, TmTcError> where T: TmTcData {
+
+    let data: T = serde_json::from_value(params)?;
+    tc_cstr.build_and_send(data)
+}
+
+// Parse the parameters of a `T` from JSON and say whether it's critical
+fn critical_params<T>(params: &serde_json::Value) -> Result<bool, TmTcError> 
+    where T: TmTcData {
+
+    let data: T = serde_json::from_value(params.clone())?;
+    Ok(data.is_critical())
+}
+
+// Parse the parameters of a `T` from JSON and arm it
+fn arm_params<T>(tc_cstr: &TcConstructor, params: serde_json::Value) 
+    -> Result<ArmedTc, TmTcError> where T: TmTcData + 'static {
+
+    let data: T = serde_json::from_value(params)?;
+    tc_cstr.arm(data)
+}
+
+// Refuse a critical TC which is being sent without being armed
+fn require_not_critical<T>(data: &T) -> Result<(), TmTcError> where 
+    T: TmTcData {
+
+    if data.is_critical() {
+        Err(TmTcError::NotConfirmed(format!(
+            "{} is critical so must be armed and then confirmed", 
+            T::TYPE_ID)))
+    }
+    else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Check a parameter is accepted at both ends of its range, and refused
+    // just outside them or if it isn't a finite number. `build` makes the TC
+    // with the parameter set to the given value and everything else valid.
+    fn check_bounds<F>(name: &str, min: f64, max: f64, build: F) where
+        F: Fn(f64) -> Result<(), TmTcError> {
+
+        for v in [min, max, 0.5 * (min + max)].iter() {
+            if let Err(e) = build(*v) {
+                panic!("{} = {} refused: {}", name, v, e);
+            }
+        }
+
+        let margin = 1e-6 * (max - min);
+        let invalid = [
+            min - margin, max + margin, f64::NAN, f64::INFINITY, 
+            f64::NEG_INFINITY];
+
+        for v in invalid.iter() {
+            match build(*v) {
+                Err(TmTcError::InvalidParameter(_)) => (),
+                r => panic!("{} = {} gave {:?}", name, v, r)
+            }
+        }
+    }
+
+    #[test]
+    fn test_drive_straight_bounds() {
+        check_bounds(
+            "distance_m", -MAX_DRIVE_DISTANCE_M, MAX_DRIVE_DISTANCE_M, 
+            |v| TcDriveStraight::new(v, 0.2).map(|_| ()));
+        check_bounds(
+            "speed_mps", MIN_DRIVE_SPEED_MPS, MAX_DRIVE_SPEED_MPS, 
+            |v| TcDriveStraight::new(1.0, v).map(|_| ()));
+    }
+
+    #[test]
+    fn test_drive_arc_bounds() {
+        check_bounds(
+            "curvature_pm", -MAX_CURVATURE_PM, MAX_CURVATURE_PM, 
+            |v| TcDriveArc::new(v, 1.0, 0.2).map(|_| ()));
+        check_bounds(
+            "distance_m", -MAX_DRIVE_DISTANCE_M, MAX_DRIVE_DISTANCE_M, 
+            |v| TcDriveArc::new(0.5, v, 0.2).map(|_| ()));
+        check_bounds(
+            "speed_mps", MIN_DRIVE_SPEED_MPS, MAX_DRIVE_SPEED_MPS, 
+            |v| TcDriveArc::new(0.5, 1.0, v).map(|_| ()));
+    }
+
+    #[test]
+    fn test_point_turn_bounds() {
+        check_bounds(
+            "angle_rad", -MAX_TURN_ANGLE_RAD, MAX_TURN_ANGLE_RAD, 
+            |v| TcPointTurn::new(v, 0.2).map(|_| ()));
+        check_bounds(
+            "rate_radps", MIN_TURN_RATE_RADPS, MAX_TURN_RATE_RADPS, 
+            |v| TcPointTurn::new(1.0, v).map(|_| ()));
+    }
+
+    #[test]
+    fn test_deserialised_tc_validated() {
+        // TCs built from JSON, as from a sequence file, bypass `new` so must
+        // still be checked before they're sent
+        let tc: TcDriveStraight = serde_json::from_str(
+            r#"{ "distance_m": 11.0, "speed_mps": 0.2 }"#).unwrap();
+
+        match tc.validate() {
+            Err(TmTcError::InvalidParameter(_)) => (),
+            r => panic!("Expected an invalid parameter but got {:?}", r)
+        }
+    }
+
+    #[test]
+    fn test_motion_tcs_critical() {
+        let registry = TcRegistry::new();
+        let is_critical = |type_id| {
+            let type_info = registry.get(type_id).unwrap();
+            let params = type_info.params_from_text(&type_info.params
+                .iter()
+                .map(|p| String::from(p.default))
+                .collect::<Vec<_>>())
+                .unwrap();
+
+            type_info.is_critical(&params).unwrap()
+        };
+
+        // Anything which moves the rover must be armed and confirmed
+        let motion = [
+            TcDriveStraight::TYPE_ID, TcDriveArc::TYPE_ID, TcPointTurn::TYPE_ID,
+            TcNavigateWaypoints::TYPE_ID, TcTraverseResume::TYPE_ID];
+
+        for type_id in motion.iter() {
+            assert!(is_critical(type_id), "{}", type_id);
+        }
+
+        // Stopping the rover must never be held up
+        let stop = [
+            TcAbort::TYPE_ID, TcTraversePause::TYPE_ID,
+            TcTraverseCancel::TYPE_ID];
+
+        for type_id in stop.iter() {
+            assert!(!is_critical(type_id), "{}", type_id);
+        }
     }
 }
@@ -0,0 +1,929 @@
+// ---------------------------------------------------------------------------
+// TELECOMMAND SEQUENCES
+//
+// Lets operators write the TCs they send every test day into a file and run
+// them as a sequence rather than sending each one by hand. A sequence file is
+// JSON, listing each step's TC by type id along with its parameters:
+//
+//     {
+//         "name": "Drive a square",
+//         "steps": [
+//             { "type_id": "TcSetMode", "params": { "mode": "Manual" } },
+//             { "type_id": "TcDriveStraight",
+//               "params": { "distance_m": 2.0, "speed_mps": 0.3 } },
+//             { "type_id": "TcPointTurn",
+//               "params": { "angle_rad": 1.5708, "rate_radps": 0.3 },
+//               "delay_s": 1.0 }
+//         ]
+//     }
+//
+// Each step is sent once the one before it has completed and the step's
+// delay has passed. A step can instead wait only for the rover to acknowledge
+// it, or just for it to be sent, using `"wait_for"`. The sequence fails if a
+// step is rejected, fails, or isn't finished within its `"timeout_s"`.
+//
+// A sequence containing critical TCs must be confirmed by the operator as a
+// whole before it's run. It's armed with `TcSequence::arm` and confirmed with
+// `ArmedSequence::confirm`, and the `TcSequenceRunner` will only run the
+// `ConfirmedSequence` this gives, or one made by
+// `ConfirmedSequence::from_non_critical` for a sequence with no critical TCs.
+//
+// Confirming the sequence only lets it start. Steps can be hours apart, so
+// the runner also arms each critical step when it's due and waits for the
+// operator to confirm that step with `TcSequenceRunner::confirm_step`, just
+// like a critical TC sent by hand. If the step isn't confirmed within
+// `ARM_TIMEOUT_S` it's disarmed and the sequence paused.
+// ---------------------------------------------------------------------------
+
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use crate::tm_tc_interface::{TcHandle, TcStatus};
+use crate::tm_tc_error::TmTcError;
+use crate::tc_constructor::{
+    TcConstructor, TcRegistry, TcTypeInfo, ArmedTc, ARM_TIMEOUT_S};
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+// How long a step may take if the file doesn't say
+const DEFAULT_STEP_TIMEOUT_S: f64 = 60.0;
+
+// Longest delay or timeout a step may have. Anything longer than a day is
+// taken to be a mistake in the file.
+const MAX_STEP_TIME_S: f64 = 24.0 * 60.0 * 60.0;
+
+// ---------------------------------------------------------------------------
+// SEQUENCE FILE
+// ---------------------------------------------------------------------------
+
+// What a step waits for before the sequence moves on to the next one
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum StepWaitFor {
+    // The TC has been sent to the rover
+    Sent,
+
+    // The rover has accepted the TC
+    Ack,
+
+    // The rover has finished executing the TC
+    Completion
+}
+
+// A sequence as it's written in the file, before it's been checked. Unknown
+// fields are refused so a misspelt one isn't silently ignored.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SequenceFile {
+    name: String,
+    steps: Vec<StepFile>
+}
+
+// A step as it's written in the file, before it's been checked
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StepFile {
+    type_id: String,
+
+    #[serde(default = "empty_params")]
+    params: serde_json::Value,
+
+    #[serde(default)]
+    delay_s: f64,
+
+    #[serde(default = "default_wait_for")]
+    wait_for: StepWaitFor,
+
+    #[serde(default = "default_timeout_s")]
+    timeout_s: f64
+}
+
+fn empty_params() -> serde_json::Value {
+    serde_json::Value::Object(serde_json::Map::new())
+}
+
+fn default_wait_for() -> StepWaitFor {
+    StepWaitFor::Completion
+}
+
+fn default_timeout_s() -> f64 {
+    DEFAULT_STEP_TIMEOUT_S
+}
+
+// ---------------------------------------------------------------------------
+// TC SEQUENCE
+// ---------------------------------------------------------------------------
+
+// A single step of a checked sequence
+struct SequenceStep {
+    type_info: TcTypeInfo,
+    params: serde_json::Value,
+    critical: bool,
+    delay: Duration,
+    wait_for: StepWaitFor,
+    timeout: Duration
+}
+
+// A sequence of TCs which has been checked against the known TC types, so
+// every step is known to be sendable.
+pub struct TcSequence {
+    name: String,
+    steps: Vec<SequenceStep>
+}
+
+impl TcSequence {
+
+    // Load a sequence from a JSON file, checking every step
+    pub fn from_file<P>(path: P, registry: &TcRegistry)
+        -> Result<Self, TmTcError> where P: AsRef<Path> {
+
+        TcSequence::from_json(&fs::read_to_string(path)?, registry)
+    }
+
+    // Parse a sequence from a JSON string, checking every step
+    pub fn from_json(json_str: &str, registry: &TcRegistry)
+        -> Result<Self, TmTcError> {
+
+        let file: SequenceFile = serde_json::from_str(json_str)?;
+
+        if file.steps.is_empty() {
+            return Err(TmTcError::InvalidSequence(format!(
+                "{} has no steps", file.name)))
+        }
+
+        let mut steps = vec![];
+
+        for (i, step) in file.steps.into_iter().enumerate() {
+            // Report which step is wrong along with what's wrong with it
+            let step_err = |e: String| TmTcError::InvalidSequence(format!(
+                "Step {} ({}): {}", i + 1, step.type_id, e));
+
+            let type_info = *registry.get(&step.type_id)
+                .map_err(|e| step_err(format!("{}", e)))?;
+
+            type_info.check(&step.params)
+                .map_err(|e| step_err(format!("{}", e)))?;
+
+            let critical = type_info.is_critical(&step.params)
+                .map_err(|e| step_err(format!("{}", e)))?;
+
+            if !(step.delay_s >= 0.0 && step.delay_s <= MAX_STEP_TIME_S) {
+                return Err(step_err(format!(
+                    "delay_s must be between 0 and {} but is {}", 
+                    MAX_STEP_TIME_S, step.delay_s)))
+            }
+
+            if !(step.timeout_s > 0.0 && step.timeout_s <= MAX_STEP_TIME_S) {
+                return Err(step_err(format!(
+                    "timeout_s must be more than 0 and at most {} but is {}", 
+                    MAX_STEP_TIME_S, step.timeout_s)))
+            }
+
+            steps.push(SequenceStep {
+                type_info,
+                params: step.params,
+                critical,
+                delay: Duration::from_secs_f64(step.delay_s),
+                wait_for: step.wait_for,
+                timeout: Duration::from_secs_f64(step.timeout_s)
+            });
+        }
+
+        Ok(TcSequence {
+            name: file.name,
+            steps
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn num_steps(&self) -> usize {
+        self.steps.len()
+    }
+
+    // Returns true if any step is a critical TC, in which case the operator
+    // must confirm the sequence before it's run
+    pub fn is_critical(&self) -> bool {
+        self.steps.iter().any(|s| s.critical)
+    }
+
+    // Arm the sequence ready to be confirmed by the operator. Any sequence
+    // can be armed, but only critical ones need to be.
+    pub fn arm(self) -> ArmedSequence {
+        ArmedSequence {
+            sequence: self,
+            armed_time: Instant::now()
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// SEQUENCE CONFIRMATION
+// ---------------------------------------------------------------------------
+
+// A sequence which has been armed by `TcSequence::arm` and is waiting for the
+// operator to confirm it. Dropping it disarms the sequence.
+pub struct ArmedSequence {
+    sequence: TcSequence,
+    armed_time: Instant
+}
+
+impl ArmedSequence {
+
+    pub fn name(&self) -> &str {
+        self.sequence.name()
+    }
+
+    // How long is left to confirm the sequence, zero once it's expired
+    pub fn time_left(&self) -> Duration {
+        Duration::from_secs(ARM_TIMEOUT_S)
+            .checked_sub(self.armed_time.elapsed())
+            .unwrap_or_default()
+    }
+
+    // Returns true if it's too late to confirm the sequence
+    pub fn is_expired(&self) -> bool {
+        self.time_left() == Duration::from_secs(0)
+    }
+
+    // Confirm the sequence so it can be run. If it was armed more than
+    // `ARM_TIMEOUT_S` ago it's refused with a `NotConfirmed` error and must
+    // be armed again.
+    pub fn confirm(self) -> Result<ConfirmedSequence, TmTcError> {
+        if self.is_expired() {
+            return Err(TmTcError::NotConfirmed(format!(
+                "Sequence {} was armed more than {} s ago, arm it again",
+                self.sequence.name, ARM_TIMEOUT_S)))
+        }
+
+        Ok(ConfirmedSequence(self.sequence))
+    }
+}
+
+// A sequence which the operator has confirmed, or which doesn't need
+// confirming, and so can be run. It can only be made by
+// `ArmedSequence::confirm` or `ConfirmedSequence::from_non_critical`.
+pub struct ConfirmedSequence(TcSequence);
+
+impl ConfirmedSequence {
+
+    // Allow a sequence with no critical TCs to be run without confirmation.
+    // A critical sequence is refused with a `NotConfirmed` error, it must be
+    // armed and then confirmed instead.
+    pub fn from_non_critical(sequence: TcSequence)
+        -> Result<Self, TmTcError> {
+
+        if sequence.is_critical() {
+            return Err(TmTcError::NotConfirmed(format!(
+                "Sequence {} contains critical TCs so must be armed and then \
+                confirmed", sequence.name)))
+        }
+
+        Ok(ConfirmedSequence(sequence))
+    }
+
+    pub fn name(&self) -> &str {
+        self.0.name()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// SEQUENCE RUNNER
+// ---------------------------------------------------------------------------
+
+// Where a sequence has got to
+#[derive(Clone, Debug, PartialEq)]
+pub enum SequenceState {
+    Running,
+
+    // The current step is a critical TC which has been armed, and is waiting
+    // for the operator to confirm it
+    AwaitingConfirmation,
+
+    Paused,
+    Completed,
+
+    // Stopped by the operator
+    Aborted,
+
+    // A step went wrong, the reason is given
+    Failed(String)
+}
+
+impl SequenceState {
+
+    // Returns true if the sequence won't send any more TCs
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, 
+            SequenceState::Running 
+            | SequenceState::AwaitingConfirmation
+            | SequenceState::Paused)
+    }
+}
+
+impl fmt::Display for SequenceState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SequenceState::Failed(r) => write!(f, "Failed: {}", r),
+            s => write!(f, "{:?}", s)
+        }
+    }
+}
+
+// A snapshot of the progress of a sequence, for showing to the operator
+#[derive(Clone, Debug)]
+pub struct SequenceProgress {
+    pub name: String,
+
+    // Number of the step being worked on, starting from 1
+    pub step: usize,
+
+    pub num_steps: usize,
+
+    // Type of the current step's TC
+    pub step_type_id: String,
+
+    // Status of the current step's TC, or None if it hasn't been sent yet
+    pub step_status: Option<TcStatus>,
+
+    pub state: SequenceState
+}
+
+impl fmt::Display for SequenceProgress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f, "{}: step {} of {} ({}",
+            self.name, self.step, self.num_steps, self.step_type_id)?;
+
+        if let Some(s) = &self.step_status {
+            write!(f, " {}", s)?;
+        }
+
+        write!(f, ") - {}", self.state)
+    }
+}
+
+// Runs a sequence one step at a time. The runner doesn't have a thread of
+// its own, instead `poll` should be called regularly (e.g. on every GUI
+// update) and sends the next step's TC when it's due.
+pub struct TcSequenceRunner {
+    sequence: TcSequence,
+
+    // Index of the step being worked on
+    step_index: usize,
+
+    // Handle of the current step's TC once it's been sent
+    step_tc: Option<TcHandle>,
+
+    // The current step's TC if it's critical and has been armed, but not yet
+    // confirmed
+    step_armed: Option<ArmedTc>,
+
+    // Time at which the current step's delay is over and it can be sent
+    step_ready_time: Instant,
+
+    // Time at which the current step's TC was sent, or the sequence was
+    // resumed, used for the step's timeout
+    step_sent_time: Instant,
+
+    state: SequenceState
+}
+
+impl TcSequenceRunner {
+
+    // Start running a confirmed sequence. Nothing is sent until `poll` is
+    // called.
+    pub fn new(sequence: ConfirmedSequence) -> Self {
+        let sequence = sequence.0;
+        let now = Instant::now();
+
+        TcSequenceRunner {
+            step_ready_time: now + sequence.steps[0].delay,
+            sequence,
+            step_index: 0,
+            step_tc: None,
+            step_armed: None,
+            step_sent_time: now,
+            state: SequenceState::Running
+        }
+    }
+
+    // Move the sequence on if the current step has finished, sending the next
+    // step's TC when it's due. A critical step is armed instead, to be sent
+    // by `confirm_step`.
+    pub fn poll(&mut self, tc_cstr: &mut TcConstructor) {
+        if self.state == SequenceState::AwaitingConfirmation {
+            let expired = match &self.step_armed {
+                Some(a) => a.is_expired(),
+                None => true
+            };
+
+            // Carrying on means confirming the step again after resuming
+            if expired {
+                eprintln!(
+                    "Sequence {} step {} wasn't confirmed in time, pausing",
+                    self.sequence.name, self.step_index + 1);
+                self.pause();
+            }
+            return
+        }
+
+        if self.state != SequenceState::Running {
+            return
+        }
+
+        let step = &self.sequence.steps[self.step_index];
+
+        if let Some(h) = &self.step_tc {
+            let status = h.status();
+
+            match status {
+                TcStatus::Rejected(_)
+                | TcStatus::Failed(_)
+                | TcStatus::Undelivered(_) => {
+                    self.state = SequenceState::Failed(format!(
+                        "Step {} ({}) {}",
+                        self.step_index + 1, step.type_info.type_id, status));
+                    return
+                },
+                _ => ()
+            }
+
+            let step_done = match step.wait_for {
+                StepWaitFor::Sent => h.frame_counter().is_some(),
+                StepWaitFor::Ack => status.is_acknowledged(),
+                StepWaitFor::Completion => status.is_final()
+            };
+
+            if !step_done {
+                if self.step_sent_time.elapsed() > step.timeout {
+                    self.state = SequenceState::Failed(format!(
+                        "Step {} ({}) timed out, still {}",
+                        self.step_index + 1, step.type_info.type_id, status));
+                }
+                return
+            }
+
+            // On to the next step
+            self.step_tc = None;
+            self.step_index += 1;
+
+            match self.sequence.steps.get(self.step_index) {
+                Some(next) => {
+                    self.step_ready_time = Instant::now() + next.delay
+                },
+                None => {
+                    // Stay on the last step so progress still refers to it
+                    self.step_index -= 1;
+                    self.state = SequenceState::Completed;
+                }
+            }
+
+            return
+        }
+
+        if Instant::now() < self.step_ready_time {
+            return
+        }
+
+        if step.critical {
+            match step.type_info.arm(tc_cstr, step.params.clone()) {
+                Ok(a) => {
+                    self.step_armed = Some(a);
+                    self.state = SequenceState::AwaitingConfirmation;
+                },
+                Err(e) => {
+                    self.state = SequenceState::Failed(format!(
+                        "Step {} ({}) could not be armed: {}",
+                        self.step_index + 1, step.type_info.type_id, e))
+                }
+            }
+            return
+        }
+
+        let sent = step.type_info.send(tc_cstr, step.params.clone());
+        self.step_sent(sent);
+    }
+
+    // Send the current step's TC, which was armed by `poll`. If there's no
+    // step waiting to be confirmed, or it was armed too long ago, it's 
+    // refused with a `NotConfirmed` error, and in the latter case the
+    // sequence is paused. A step which can't be sent fails the sequence like
+    // any other.
+    pub fn confirm_step(&mut self, tc_cstr: &mut TcConstructor) 
+        -> Result<(), TmTcError> {
+
+        let armed = match self.step_armed.take() {
+            Some(a) if self.state == SequenceState::AwaitingConfirmation => a,
+            _ => return Err(TmTcError::NotConfirmed(format!(
+                "Sequence {} has no step waiting to be confirmed",
+                self.sequence.name)))
+        };
+
+        if armed.is_expired() {
+            self.pause();
+            return Err(TmTcError::NotConfirmed(format!(
+                "Sequence {} step {} was armed more than {} s ago, resume the \
+                sequence to arm it again",
+                self.sequence.name, self.step_index + 1, ARM_TIMEOUT_S)))
+        }
+
+        self.state = SequenceState::Running;
+        let sent = tc_cstr.confirm(armed);
+        self.step_sent(sent);
+        Ok(())
+    }
+
+    // How long is left to confirm the current step, or None if it isn't
+    // waiting to be confirmed
+    pub fn confirm_time_left(&self) -> Option<Duration> {
+        self.step_armed.as_ref().map(|a| a.time_left())
+    }
+
+    // Record the result of sending the current step's TC
+    fn step_sent(&mut self, sent: Result<TcHandle, TmTcError>) {
+        let step = &self.sequence.steps[self.step_index];
+
+        match sent {
+            Ok(h) => {
+                self.step_tc = Some(h);
+                self.step_sent_time = Instant::now();
+            },
+            Err(e) => {
+                self.state = SequenceState::Failed(format!(
+                    "Step {} ({}) could not be sent: {}",
+                    self.step_index + 1, step.type_info.type_id, e))
+            }
+        }
+    }
+
+    // Stop sending TCs until `resume` is called. A TC which has already been
+    // sent carries on, and one waiting to be confirmed is disarmed.
+    pub fn pause(&mut self) {
+        match self.state {
+            SequenceState::Running 
+            | SequenceState::AwaitingConfirmation => {
+                self.step_armed = None;
+                self.state = SequenceState::Paused;
+            },
+            _ => ()
+        }
+    }
+
+    // Carry on with a paused sequence. If the current step hadn't been sent
+    // yet its delay starts again, otherwise its timeout does.
+    pub fn resume(&mut self) {
+        if self.state == SequenceState::Paused {
+            let now = Instant::now();
+            self.step_ready_time = now
+                + self.sequence.steps[self.step_index].delay;
+            self.step_sent_time = now;
+            self.state = SequenceState::Running;
+        }
+    }
+
+    // Stop the sequence for good. This doesn't stop the rover, send a
+    // `TcAbort` for that.
+    pub fn abort(&mut self) {
+        if !self.state.is_finished() {
+            self.step_armed = None;
+            self.state = SequenceState::Aborted;
+        }
+    }
+
+    pub fn state(&self) -> &SequenceState {
+        &self.state
+    }
+
+    pub fn progress(&self) -> SequenceProgress {
+        SequenceProgress {
+            name: self.sequence.name.clone(),
+            step: self.step_index + 1,
+            num_steps: self.sequence.num_steps(),
+            step_type_id: String::from(
+                self.sequence.steps[self.step_index].type_info.type_id),
+            step_status: self.step_tc.as_ref().map(|h| h.status()),
+            state: self.state.clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+    use chrono::Utc;
+    use crate::tm_tc_interface::{AutoTmTc, TmTcIf, TmTcIfConfig};
+    use crate::tm_tc_framing::{FramingMode, FrameDecoder, encode_frame};
+    use crate::tm_deconstructor::{TmTcAck, TcAckStatus};
+    use crate::tc_constructor::TcHeartbeat;
+
+    // A step which is always valid and never critical
+    const STANDBY_STEP: &str = 
+        r#"{ "type_id": "TcSetMode", "params": { "mode": "Standby" } }"#;
+
+    fn parse(steps: &[&str]) -> Result<TcSequence, TmTcError> {
+        let json_str = format!(
+            r#"{{ "name": "Test", "steps": [{}] }}"#, steps.join(","));
+
+        TcSequence::from_json(&json_str, &TcRegistry::new())
+    }
+
+    // Parse a sequence with no critical steps, ready to run
+    fn confirmed(steps: &[&str]) -> ConfirmedSequence {
+        ConfirmedSequence::from_non_critical(parse(steps).unwrap()).unwrap()
+    }
+
+    // Check a sequence is refused as invalid
+    fn assert_invalid(steps: &[&str]) {
+        match parse(steps) {
+            Err(TmTcError::InvalidSequence(_)) => (),
+            Err(e) => panic!("Expected an invalid sequence but got {}", e),
+            Ok(_) => panic!("Expected an invalid sequence but it parsed")
+        }
+    }
+
+    // Start a rover which acknowledges every TC other than heartbeats with
+    // each of `acks` in turn, and a constructor connected to it
+    fn fake_rover(acks: Vec<TcAckStatus>) -> TcConstructor {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            let (mut stream, _) = match listener.accept() {
+                Ok(s) => s,
+                Err(_) => return
+            };
+            let mut decoder = FrameDecoder::new(
+                FramingMode::NewlineDelimited, 1 << 16);
+            let mut buf = [0u8; 4096];
+            let mut tm_frame_counter = 0;
+
+            while let Ok(n) = stream.read(&mut buf) {
+                if n == 0 {
+                    return
+                }
+                decoder.push(&buf[..n]);
+
+                while let Some(Ok(frame)) = decoder.next_frame() {
+                    let tc = AutoTmTc::from_json(
+                        &String::from_utf8(frame).unwrap()).unwrap();
+
+                    if tc.is::<TcHeartbeat>() {
+                        continue
+                    }
+
+                    for status in acks.iter() {
+                        let mut tm = AutoTmTc::from_data(&TmTcAck {
+                            frame_counter: tc.frame_counter().unwrap(),
+                            status: status.clone()
+                        }).unwrap();
+                        tm.stamp(tm_frame_counter, Utc::now());
+                        tm_frame_counter += 1;
+
+                        let frame = encode_frame(
+                            FramingMode::NewlineDelimited,
+                            tm.to_json().unwrap().as_bytes()).unwrap();
+                        if stream.write_all(&frame).is_err() {
+                            return
+                        }
+                    }
+                }
+            }
+        });
+
+        TcConstructor::new(TmTcIf::start(TmTcIfConfig {
+            rover_port: port,
+            disconnect_timeout: Duration::from_millis(100),
+            ..TmTcIfConfig::default()
+        }))
+    }
+
+    // Poll the runner until its sequence finishes
+    fn run_to_end(runner: &mut TcSequenceRunner, tc_cstr: &mut TcConstructor) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while !runner.state().is_finished() {
+            assert!(Instant::now() < deadline, "Sequence never finished");
+            runner.poll(tc_cstr);
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    #[test]
+    fn test_parse_sequence() {
+        let seq = parse(&[
+            STANDBY_STEP,
+            r#"{ "type_id": "TcSetMode", "params": { "mode": "Manual" },
+                 "delay_s": 1.5, "wait_for": "Ack", "timeout_s": 10.0 }"#
+        ]).unwrap();
+
+        assert_eq!(seq.name(), "Test");
+        assert_eq!(seq.num_steps(), 2);
+        assert_eq!(seq.steps[1].delay, Duration::from_millis(1500));
+        assert_eq!(seq.steps[1].wait_for, StepWaitFor::Ack);
+        assert_eq!(seq.steps[1].timeout, Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_invalid(&[]);
+        assert_invalid(&[r#"{ "type_id": "TcFly" }"#]);
+        assert_invalid(&[r#"{ "type_id": "TcDriveStraight",
+            "params": { "distance_m": 100.0, "speed_mps": 0.2 } }"#]);
+        assert_invalid(&[r#"{ "type_id": "TcDriveStraight",
+            "params": { "distance_m": 1.0 } }"#]);
+    }
+
+    #[test]
+    fn test_parse_bad_delay_and_timeout() {
+        for field in ["delay_s", "timeout_s"].iter() {
+            for value in ["-1.0", "1e30"].iter() {
+                assert_invalid(&[&format!(
+                    r#"{{ "type_id": "TcSetMode", 
+                         "params": {{ "mode": "Standby" }}, "{}": {} }}"#,
+                    field, value)]);
+            }
+        }
+
+        assert_invalid(&[r#"{ "type_id": "TcSetMode", 
+            "params": { "mode": "Standby" }, "timeout_s": 0.0 }"#]);
+    }
+
+    #[test]
+    fn test_parse_unknown_fields() {
+        // A misspelt field in a step or the sequence itself is refused
+        assert!(parse(&[r#"{ "type_id": "TcSetMode", 
+            "params": { "mode": "Standby" }, "delay": 1.0 }"#]).is_err());
+
+        let json_str = format!(
+            r#"{{ "name": "Test", "step": [], "steps": [{}] }}"#, STANDBY_STEP);
+        assert!(TcSequence::from_json(&json_str, &TcRegistry::new()).is_err());
+    }
+
+    #[test]
+    fn test_sequence_is_critical() {
+        assert!(!parse(&[STANDBY_STEP]).unwrap().is_critical());
+
+        assert!(parse(&[STANDBY_STEP, r#"{ "type_id": "TcDriveStraight",
+            "params": { "distance_m": 1.0, "speed_mps": 0.2 } }"#])
+            .unwrap().is_critical());
+
+        assert!(parse(&[r#"{ "type_id": "TcSetMode", 
+            "params": { "mode": "Autonomous" } }"#])
+            .unwrap().is_critical());
+    }
+
+    #[test]
+    fn test_runner_completes() {
+        let mut tc_cstr = fake_rover(
+            vec![TcAckStatus::Accepted, TcAckStatus::Completed]);
+        let mut runner = TcSequenceRunner::new(
+            confirmed(&[STANDBY_STEP, STANDBY_STEP]));
+
+        run_to_end(&mut runner, &mut tc_cstr);
+
+        assert_eq!(*runner.state(), SequenceState::Completed);
+        assert_eq!(tc_cstr.history().len(), 2);
+        assert_eq!(runner.progress().step, 2);
+        tc_cstr.stop().unwrap();
+    }
+
+    #[test]
+    fn test_runner_pause_resume_abort() {
+        let mut tc_cstr = fake_rover(vec![]);
+        let mut runner = TcSequenceRunner::new(
+            confirmed(&[STANDBY_STEP, STANDBY_STEP]));
+
+        // Nothing is sent while paused
+        runner.pause();
+        runner.poll(&mut tc_cstr);
+        assert_eq!(*runner.state(), SequenceState::Paused);
+        assert!(tc_cstr.history().is_empty());
+
+        runner.resume();
+        runner.poll(&mut tc_cstr);
+        assert_eq!(*runner.state(), SequenceState::Running);
+        assert_eq!(tc_cstr.history().len(), 1);
+
+        // Once aborted the sequence can't be resumed
+        runner.abort();
+        runner.resume();
+        runner.poll(&mut tc_cstr);
+        assert_eq!(*runner.state(), SequenceState::Aborted);
+        assert!(runner.state().is_finished());
+        assert_eq!(tc_cstr.history().len(), 1);
+        tc_cstr.stop().unwrap();
+    }
+
+    #[test]
+    fn test_runner_step_timeout() {
+        // The rover never acknowledges anything
+        let mut tc_cstr = fake_rover(vec![]);
+        let mut runner = TcSequenceRunner::new(confirmed(&[r#"{
+            "type_id": "TcSetMode", "params": { "mode": "Standby" },
+            "timeout_s": 0.05 }"#]));
+
+        run_to_end(&mut runner, &mut tc_cstr);
+
+        match runner.state() {
+            SequenceState::Failed(r) => {
+                assert!(r.contains("timed out"), "{}", r)
+            },
+            s => panic!("Expected the step to time out but got {}", s)
+        }
+        tc_cstr.stop().unwrap();
+    }
+
+    #[test]
+    fn test_runner_fails_on_reject() {
+        let mut tc_cstr = fake_rover(vec![TcAckStatus::Rejected { 
+            reason: String::from("Not today") }]);
+        let mut runner = TcSequenceRunner::new(
+            confirmed(&[STANDBY_STEP, STANDBY_STEP]));
+
+        run_to_end(&mut runner, &mut tc_cstr);
+
+        match runner.state() {
+            SequenceState::Failed(r) => {
+                assert!(r.contains("Not today"), "{}", r)
+            },
+            s => panic!("Expected the step to be rejected but got {}", s)
+        }
+
+        // The second step is never sent
+        assert_eq!(runner.progress().step, 1);
+        assert_eq!(tc_cstr.history().len(), 1);
+        tc_cstr.stop().unwrap();
+    }
+
+    #[test]
+    fn test_critical_sequence_needs_confirming() {
+        let drive = r#"{ "type_id": "TcDriveStraight",
+            "params": { "distance_m": 1.0, "speed_mps": 0.2 } }"#;
+
+        match ConfirmedSequence::from_non_critical(parse(&[drive]).unwrap()) {
+            Err(TmTcError::NotConfirmed(_)) => (),
+            Err(e) => panic!("Expected not confirmed but got {}", e),
+            Ok(_) => panic!("Critical sequence allowed without confirming")
+        }
+
+        // Too late to confirm
+        let expired = ArmedSequence {
+            sequence: parse(&[drive]).unwrap(),
+            armed_time: Instant::now() - Duration::from_secs(ARM_TIMEOUT_S)
+        };
+        assert!(expired.is_expired());
+        match expired.confirm() {
+            Err(TmTcError::NotConfirmed(_)) => (),
+            Err(e) => panic!("Expected not confirmed but got {}", e),
+            Ok(_) => panic!("Expired sequence was confirmed")
+        }
+
+        let armed = parse(&[drive]).unwrap().arm();
+        assert_eq!(armed.name(), "Test");
+        assert!(!armed.is_expired());
+        assert!(armed.confirm().is_ok());
+    }
+
+    #[test]
+    fn test_runner_waits_for_critical_step_confirmation() {
+        let mut tc_cstr = fake_rover(
+            vec![TcAckStatus::Accepted, TcAckStatus::Completed]);
+        let sequence = parse(&[r#"{ "type_id": "TcDriveStraight",
+            "params": { "distance_m": 1.0, "speed_mps": 0.2 } }"#]).unwrap();
+        let mut runner = TcSequenceRunner::new(
+            sequence.arm().confirm().unwrap());
+
+        // Confirming the sequence isn't enough to send the step
+        assert!(runner.confirm_step(&mut tc_cstr).is_err());
+        runner.poll(&mut tc_cstr);
+        runner.poll(&mut tc_cstr);
+        assert_eq!(*runner.state(), SequenceState::AwaitingConfirmation);
+        assert!(runner.confirm_time_left().is_some());
+        assert!(tc_cstr.history().is_empty());
+
+        // Pausing disarms the step, and resuming arms it again
+        runner.pause();
+        assert!(runner.confirm_step(&mut tc_cstr).is_err());
+        assert!(runner.confirm_time_left().is_none());
+        runner.resume();
+        runner.poll(&mut tc_cstr);
+        assert_eq!(*runner.state(), SequenceState::AwaitingConfirmation);
+
+        runner.confirm_step(&mut tc_cstr).unwrap();
+        assert_eq!(tc_cstr.history()[0].type_id, "TcDriveStraight");
+        run_to_end(&mut runner, &mut tc_cstr);
+
+        assert_eq!(*runner.state(), SequenceState::Completed);
+        assert_eq!(tc_cstr.history().len(), 1);
+        tc_cstr.stop().unwrap();
+    }
+}
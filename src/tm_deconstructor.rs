@@ -0,0 +1,407 @@
+// ---------------------------------------------------------------------------
+// TELEMETRY DECONSTRUCTOR
+//
+// Takes the telemetry received by the TmTcInterface module and turns it back
+// into data the GUI can use. Each TM type the GUI is interested in is
+// registered with the deconstructor, either with a callback which is run as
+// soon as the TM is processed, or with a queue which the GUI can read from
+// when it's ready. TMs of any other type are reported as unknown rather than
+// being parsed as the wrong thing.
+//
+// Diferent types of telemetry are defined as structs here.
+// ---------------------------------------------------------------------------
+
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, VecDeque};
+use crate::tm_tc_interface::{TmTcIf, TmTcData, AutoTmTc, ExecTime};
+use crate::tm_tc_error::TmTcError;
+use crate::tm_tc_types::RoverMode;
+
+// ---------------------------------------------------------------------------
+// TM DECONSTRUCTOR
+// ---------------------------------------------------------------------------
+
+// A callback which parses a TM and does something with its data
+type TmCallback = Box<dyn FnMut(&AutoTmTc) -> Result<(), TmTcError>>;
+
+// What to do with a TM of a particular type
+enum TmHandler {
+    // Parse the TM and pass it to a callback straight away
+    Callback(TmCallback),
+
+    // Keep the TM until it's asked for with `get_pending`
+    Queue(VecDeque<AutoTmTc>),
+
+    // Throw the TM away, used for TMs which the TmTcIf deals with itself
+    Ignore
+}
+
+pub struct TmDeconstructor {
+
+    // Handlers for each registered TM type, keyed by type id
+    handlers: HashMap<String, TmHandler>,
+
+    // TMs received with a type id that hasn't been registered
+    unknown: VecDeque<AutoTmTc>
+
+}
+
+impl TmDeconstructor {
+
+    // Instantiate a new `TmDeconstructor`. TMs which are only of interest to
+    // the TmTcIf, like heartbeats, are ignored by default.
+    pub fn new() -> Self {
+        let mut tm_dcstr = TmDeconstructor {
+            handlers: HashMap::new(),
+            unknown: VecDeque::new()
+        };
+
+        tm_dcstr.ignore::<TmHeartbeat>();
+        tm_dcstr.ignore::<TmDisconnectAck>();
+        tm_dcstr.ignore::<TmTcAck>();
+
+        tm_dcstr
+    }
+
+    // Register a callback to be run with the data of every TM of type `T`.
+    // Replaces any existing handler for `T`.
+    pub fn register_callback<T, F>(&mut self, mut callback: F) where
+        T: TmTcData + 'static,
+        F: FnMut(T) + 'static {
+
+        self.handlers.insert(
+            String::from(T::TYPE_ID),
+            TmHandler::Callback(Box::new(move |tm| {
+                callback(tm.parse_data::<T>()?);
+                Ok(())
+            })));
+    }
+
+    // Register a queue for TMs of type `T`, which can be read from using
+    // `get_pending::<T>`. Replaces any existing handler for `T`.
+    pub fn register_queue<T>(&mut self) where T: TmTcData {
+        self.handlers.insert(
+            String::from(T::TYPE_ID), TmHandler::Queue(VecDeque::new()));
+    }
+
+    // Silently discard all TMs of type `T`. Replaces any existing handler
+    // for `T`.
+    pub fn ignore<T>(&mut self) where T: TmTcData {
+        self.handlers.insert(String::from(T::TYPE_ID), TmHandler::Ignore);
+    }
+
+    // Read all pending TMs from the interface and pass each one to the
+    // handler for its type. Returns the number of TMs read. A TM which can't
+    // be parsed is reported and skipped without stopping the others.
+    pub fn process(&mut self, tm_tc_if: &TmTcIf) -> Result<usize, TmTcError> {
+        let mut num_tms = 0;
+
+        while let Some(tm) = tm_tc_if.get_pending_tm()? {
+            if let Err(e) = self.dispatch(tm) {
+                eprintln!("Failed to handle TM: {}", e);
+            }
+            num_tms += 1;
+        }
+
+        Ok(num_tms)
+    }
+
+    // Pass a single TM to the handler for its type
+    pub fn dispatch(&mut self, tm: AutoTmTc) -> Result<(), TmTcError> {
+        match self.handlers.get_mut(tm.data_type_id()) {
+            Some(TmHandler::Callback(c)) => c(&tm),
+            Some(TmHandler::Queue(q)) => {
+                q.push_back(tm);
+                Ok(())
+            },
+            Some(TmHandler::Ignore) => Ok(()),
+            None => {
+                let err = TmTcError::UnknownTypeId(
+                    String::from(tm.data_type_id()));
+                self.unknown.push_back(tm);
+                Err(err)
+            }
+        }
+    }
+
+    // Get the oldest queued TM of type `T`, or None if there aren't any.
+    // Returns an error if `T` hasn't been registered with `register_queue`.
+    pub fn get_pending<T>(&mut self) -> Result<Option<T>, TmTcError> where
+        T: TmTcData {
+
+        match self.handlers.get_mut(T::TYPE_ID) {
+            Some(TmHandler::Queue(q)) => match q.pop_front() {
+                Some(tm) => tm.parse_data::<T>().map(Some),
+                None => Ok(None)
+            },
+            _ => Err(TmTcError::UnknownTypeId(String::from(T::TYPE_ID)))
+        }
+    }
+
+    // Get the oldest TM which had an unknown type, or None if there aren't
+    // any.
+    pub fn get_unknown_tm(&mut self) -> Option<AutoTmTc> {
+        self.unknown.pop_front()
+    }
+}
+
+impl Default for TmDeconstructor {
+    fn default() -> Self {
+        TmDeconstructor::new()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TM TYPES
+// ---------------------------------------------------------------------------
+
+// HEARTBEAT
+//
+// Sent periodically by the rover to show it is still alive, contains the
+// rover's current time
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TmHeartbeat {
+    pub current_time_utc: DateTime<Utc>
+}
+
+impl TmTcData for TmHeartbeat {
+    const TYPE_ID: &'static str = "TmHeartbeat";
+}
+
+// DISCONNECT ACKNOWLEDGEMENT
+//
+// Sent by the rover in response to a `TcDisconnect`, just before it closes
+// the connection
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TmDisconnectAck {}
+
+impl TmTcData for TmDisconnectAck {
+    const TYPE_ID: &'static str = "TmDisconnectAck";
+}
+
+// TC ACKNOWLEDGEMENT
+//
+// Sent by the rover as a TC progresses, referring to the TC by the frame
+// counter it was sent with. Normally a TC is first accepted or rejected, and
+// once accepted later completed or failed.
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum TcAckStatus {
+    Accepted,
+    Rejected { reason: String },
+    Completed,
+    Failed { reason: String }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TmTcAck {
+    pub frame_counter: u32,
+    pub status: TcAckStatus
+}
+
+impl TmTcData for TmTcAck {
+    const TYPE_ID: &'static str = "TmTcAck";
+}
+
+// ROVER POSE
+//
+// The rover's position and attitude in the world frame at a given time
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TmRoverPose {
+    // Position of the rover's body centre in metres
+    pub position_m: [f64; 3],
+
+    // Attitude of the rover as a unit quaternion, in [x, y, z, w] order
+    pub attitude_q: [f64; 4],
+
+    // Time at which the pose was measured
+    pub timestamp_utc: DateTime<Utc>
+}
+
+impl TmTcData for TmRoverPose {
+    const TYPE_ID: &'static str = "TmRoverPose";
+}
+
+// ROVER HEALTH
+//
+// General housekeeping data on the state of the rover's hardware
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TmRoverHealth {
+    // Voltage of the main battery
+    pub battery_voltage_v: f64,
+
+    // Remaining battery charge as a percentage
+    pub battery_charge_pct: f64,
+
+    // Temperature of the on-board computer
+    pub cpu_temp_c: f64
+}
+
+impl TmTcData for TmRoverHealth {
+    const TYPE_ID: &'static str = "TmRoverHealth";
+}
+
+// ROVER MODE
+//
+// The rover's current mode, sent periodically and whenever the mode changes
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TmRoverMode {
+    pub mode: RoverMode
+}
+
+impl TmTcData for TmRoverMode {
+    const TYPE_ID: &'static str = "TmRoverMode";
+}
+
+// SCHEDULE
+//
+// The contents of the rover's on-board schedule, sent in response to a
+// `TcScheduleList`. Entries are in the order they'll be executed, as far as
+// the rover knows.
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduleEntry {
+    // Frame counter the scheduled TC was sent with
+    pub frame_counter: u32,
+
+    // Type of the scheduled TC
+    pub data_type_id: String,
+
+    // When the TC will be executed
+    pub exec_time: ExecTime
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TmSchedule {
+    pub entries: Vec<ScheduleEntry>
+}
+
+impl TmTcData for TmSchedule {
+    const TYPE_ID: &'static str = "TmSchedule";
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // Build a TM as it would arrive from the rover, from the raw JSON frame
+    fn raw_tm(frame_counter: u32, type_id: &str, data: &str) -> AutoTmTc {
+        let frame = format!(
+            r#"{{"frame_counter":{},"send_time_utc":"2020-01-01T00:00:00Z","data_type_id":"{}","data":{}}}"#,
+            frame_counter, type_id, serde_json::to_string(data).unwrap());
+
+        AutoTmTc::from_json(&frame).unwrap()
+    }
+
+    fn health_tm(frame_counter: u32, voltage: f64) -> AutoTmTc {
+        raw_tm(frame_counter, "TmRoverHealth", &format!(
+            r#"{{"battery_voltage_v":{},"battery_charge_pct":50.0,"cpu_temp_c":40.0}}"#,
+            voltage))
+    }
+
+    #[test]
+    fn test_callback_dispatch() {
+        let mut tm_dcstr = TmDeconstructor::new();
+        let modes = Rc::new(RefCell::new(vec![]));
+        let modes_cb = modes.clone();
+
+        tm_dcstr.register_callback::<TmRoverMode, _>(move |tm| {
+            modes_cb.borrow_mut().push(tm.mode)
+        });
+
+        tm_dcstr.dispatch(raw_tm(0, "TmRoverMode", r#"{"mode":"Manual"}"#))
+            .unwrap();
+        tm_dcstr.dispatch(raw_tm(1, "TmRoverMode", r#"{"mode":"Safe"}"#))
+            .unwrap();
+
+        assert_eq!(*modes.borrow(), vec![RoverMode::Manual, RoverMode::Safe]);
+    }
+
+    #[test]
+    fn test_callback_parse_error() {
+        let mut tm_dcstr = TmDeconstructor::new();
+        let calls = Rc::new(RefCell::new(0));
+        let calls_cb = calls.clone();
+
+        tm_dcstr.register_callback::<TmRoverMode, _>(move |_| {
+            *calls_cb.borrow_mut() += 1
+        });
+
+        // A bad TM is reported without reaching the callback, and doesn't
+        // stop the next one
+        assert!(tm_dcstr.dispatch(
+            raw_tm(0, "TmRoverMode", r#"{"mode":"Flying"}"#)).is_err());
+        tm_dcstr.dispatch(raw_tm(1, "TmRoverMode", r#"{"mode":"Standby"}"#))
+            .unwrap();
+
+        assert_eq!(*calls.borrow(), 1);
+    }
+
+    #[test]
+    fn test_queue_dispatch() {
+        let mut tm_dcstr = TmDeconstructor::new();
+        tm_dcstr.register_queue::<TmRoverHealth>();
+
+        tm_dcstr.dispatch(health_tm(0, 12.0)).unwrap();
+        tm_dcstr.dispatch(health_tm(1, 11.5)).unwrap();
+
+        // Queued TMs come out oldest first
+        let voltages: Vec<f64> = std::iter::from_fn(|| {
+                tm_dcstr.get_pending::<TmRoverHealth>().unwrap()
+            })
+            .map(|h| h.battery_voltage_v)
+            .collect();
+
+        assert_eq!(voltages, vec![12.0, 11.5]);
+        assert!(tm_dcstr.get_pending::<TmRoverHealth>().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_ignored_tms() {
+        let mut tm_dcstr = TmDeconstructor::new();
+
+        // Heartbeats are ignored by default, and so is anything ignored later
+        tm_dcstr.ignore::<TmRoverHealth>();
+
+        let heartbeat = r#"{"current_time_utc":"2020-01-01T00:00:00Z"}"#;
+        tm_dcstr.dispatch(raw_tm(0, "TmHeartbeat", heartbeat)).unwrap();
+        tm_dcstr.dispatch(health_tm(1, 12.0)).unwrap();
+
+        assert!(tm_dcstr.get_unknown_tm().is_none());
+
+        // An ignored type has no queue to read from
+        match tm_dcstr.get_pending::<TmRoverHealth>() {
+            Err(TmTcError::UnknownTypeId(_)) => (),
+            r => panic!("Expected an unknown type id but got {:?}", r)
+        }
+    }
+
+    #[test]
+    fn test_unknown_type_id() {
+        let mut tm_dcstr = TmDeconstructor::new();
+
+        match tm_dcstr.dispatch(raw_tm(0, "TmSomethingNew", "{}")) {
+            Err(TmTcError::UnknownTypeId(t)) => assert_eq!(t, "TmSomethingNew"),
+            r => panic!("Expected an unknown type id but got {:?}", r)
+        }
+
+        // Unknown TMs are kept rather than parsed as the wrong thing
+        let unknown = tm_dcstr.get_unknown_tm().unwrap();
+        assert_eq!(unknown.data_type_id(), "TmSomethingNew");
+        assert_eq!(unknown.frame_counter(), Some(0));
+        assert!(tm_dcstr.get_unknown_tm().is_none());
+
+        // A type which was only registered later is handled from then on
+        tm_dcstr.register_queue::<TmRoverHealth>();
+        tm_dcstr.dispatch(health_tm(1, 12.0)).unwrap();
+        assert!(tm_dcstr.get_unknown_tm().is_none());
+    }
+}
@@ -0,0 +1,127 @@
+// ---------------------------------------------------------------------------
+// TELEMETRY AND TELECOMMAND ERRORS
+//
+// A single error type for everything that can go wrong between the GUI and
+// the rover, so that callers can decide whether to retry, alert the operator
+// or give up depending on what actually happened.
+// ---------------------------------------------------------------------------
+
+use std::fmt;
+use std::error::Error;
+use std::io;
+use crate::tm_tc_framing::FrameError;
+
+// ---------------------------------------------------------------------------
+// TMTC ERROR
+// ---------------------------------------------------------------------------
+
+#[derive(Debug)]
+pub enum TmTcError {
+    // Data couldn't be serialised to or parsed from JSON
+    Serde(serde_json::Error),
+
+    // A problem with the TCP connection to the rover
+    Io(io::Error),
+
+    // A channel between the interface's front end and backend has closed,
+    // meaning the other end has stopped
+    ChannelClosed,
+
+    // The backend thread panicked and couldn't be joined
+    BackendPanicked,
+
+    // No handler or queue is registered for this type id
+    UnknownTypeId(String),
+
+    // A packet held a different type of data to the one asked for
+    TypeMismatch { expected: String, found: String },
+
+    // Data received from the rover doesn't follow the protocol, for example
+    // bad framing or invalid UTF-8
+    Protocol(String),
+
+    // Something didn't happen within the time allowed
+    Timeout(String),
+
+    // A TC parameter is outside the range the rover will accept
+    InvalidParameter(String),
+
+    // A TC can't be executed in the rover's current mode
+    ModeViolation(String),
+
+    // A TC sequence file doesn't describe a valid sequence
+    InvalidSequence(String),
+
+    // A critical TC was sent without being armed and confirmed, or the
+    // confirmation came too late
+    NotConfirmed(String)
+}
+
+impl TmTcError {
+
+    // Returns true if the same operation might succeed if tried again later,
+    // for example once the link has been re-established.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, TmTcError::Io(_) | TmTcError::Timeout(_))
+    }
+
+    // Returns true if the interface can no longer be used and must be
+    // restarted.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, TmTcError::ChannelClosed | TmTcError::BackendPanicked)
+    }
+}
+
+impl fmt::Display for TmTcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TmTcError::Serde(e) => write!(f, "Serialisation error: {}", e),
+            TmTcError::Io(e) => write!(f, "Network error: {}", e),
+            TmTcError::ChannelClosed => write!(
+                f, "Internal channel disconnected"),
+            TmTcError::BackendPanicked => write!(
+                f, "TmTcIf backend thread panicked"),
+            TmTcError::UnknownTypeId(t) => write!(f, "Unknown type id {}", t),
+            TmTcError::TypeMismatch { expected, found } => write!(
+                f, "Expected {} data but found {}", expected, found),
+            TmTcError::Protocol(s) => write!(f, "Protocol violation: {}", s),
+            TmTcError::Timeout(s) => write!(f, "Timed out: {}", s),
+            TmTcError::InvalidParameter(s) => write!(
+                f, "Invalid parameter: {}", s),
+            TmTcError::ModeViolation(s) => write!(
+                f, "Not allowed in the rover's current mode: {}", s),
+            TmTcError::InvalidSequence(s) => write!(
+                f, "Invalid TC sequence: {}", s),
+            TmTcError::NotConfirmed(s) => write!(
+                f, "Critical TC not confirmed: {}", s)
+        }
+    }
+}
+
+impl Error for TmTcError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TmTcError::Serde(e) => Some(e),
+            TmTcError::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<serde_json::Error> for TmTcError {
+    fn from(e: serde_json::Error) -> Self {
+        TmTcError::Serde(e)
+    }
+}
+
+impl From<io::Error> for TmTcError {
+    fn from(e: io::Error) -> Self {
+        TmTcError::Io(e)
+    }
+}
+
+impl From<FrameError> for TmTcError {
+    fn from(e: FrameError) -> Self {
+        TmTcError::Protocol(format!("{}", e))
+    }
+}
@@ -0,0 +1,377 @@
+// ---------------------------------------------------------------------------
+// TELEMETRY AND TELECOMMAND FRAMING
+//
+// Defines how `AutoTmTc` packets are split up on the TCP byte stream between
+// the TmTcIf and the rover's AutonomyManager. Two modes are supported:
+//
+//  - Newline delimited: each packet is a single line of JSON terminated by a
+//    `\n`. Compact JSON never contains a raw newline so this is unambiguous.
+//  - Length prefixed: each packet is preceded by a two byte sync word and a
+//    four byte big-endian payload length.
+//
+// The `FrameDecoder` accepts data in whatever chunks the socket gives us and
+// hands back complete frames, recovering from oversize or garbage data by
+// skipping forward to the start of the next frame.
+// ---------------------------------------------------------------------------
+
+use std::fmt;
+use crate::tm_tc_error::TmTcError;
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+// Sync word marking the start of a length prefixed frame
+const SYNC_WORD: [u8; 2] = [0xA5, 0x5A];
+
+// Length of the sync word plus the length field
+const LENGTH_PREFIX_HEADER_LEN: usize = 6;
+
+// Default maximum length of a single frame's payload in bytes
+pub const DEFAULT_MAX_FRAME_LEN: usize = 1024 * 1024;
+
+// ---------------------------------------------------------------------------
+// FRAMING MODE
+// ---------------------------------------------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FramingMode {
+    NewlineDelimited,
+    LengthPrefixed
+}
+
+// Encode a payload into a single frame ready to be written to the stream.
+pub fn encode_frame(
+    mode: FramingMode, payload: &[u8]) -> Result<Vec<u8>, TmTcError> {
+    match mode {
+        FramingMode::NewlineDelimited => {
+            if payload.contains(&b'\n') {
+                return Err(TmTcError::Protocol(String::from(
+                    "Payload contains a newline and cannot be newline delimited")))
+            }
+
+            let mut frame = Vec::with_capacity(payload.len() + 1);
+            frame.extend_from_slice(payload);
+            frame.push(b'\n');
+            Ok(frame)
+        },
+        FramingMode::LengthPrefixed => {
+            if payload.len() > u32::MAX as usize {
+                return Err(TmTcError::Protocol(format!(
+                    "Payload of {} bytes is too long to be length prefixed",
+                    payload.len())))
+            }
+
+            let mut frame = Vec::with_capacity(
+                payload.len() + LENGTH_PREFIX_HEADER_LEN);
+            frame.extend_from_slice(&SYNC_WORD);
+            frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+            frame.extend_from_slice(payload);
+            Ok(frame)
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FRAME ERRORS
+// ---------------------------------------------------------------------------
+
+// Problems found in the byte stream. None of these are fatal, the decoder has
+// already skipped past the bad data by the time one is returned.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FrameError {
+    // A frame was longer than the maximum frame length and was discarded
+    Oversize { len: usize },
+
+    // Bytes were skipped while searching for the start of the next frame
+    Garbage { skipped: usize }
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FrameError::Oversize { len } => write!(
+                f, "Discarded oversize frame of {} bytes", len),
+            FrameError::Garbage { skipped } => write!(
+                f, "Skipped {} bytes of garbage between frames", skipped)
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// FRAME DECODER
+// ---------------------------------------------------------------------------
+
+pub struct FrameDecoder {
+
+    // The framing mode in use on the stream
+    mode: FramingMode,
+
+    // Frames with payloads longer than this are discarded
+    max_frame_len: usize,
+
+    // Data received which hasn't yet been returned as a frame
+    buffer: Vec<u8>,
+
+    // Number of bytes thrown away from an oversize line which hasn't ended
+    // yet. Only used in newline delimited mode.
+    discarded_len: Option<usize>
+}
+
+impl FrameDecoder {
+
+    pub fn new(mode: FramingMode, max_frame_len: usize) -> Self {
+        FrameDecoder {
+            mode,
+            max_frame_len,
+            buffer: vec![],
+            discarded_len: None
+        }
+    }
+
+    // Add newly received data to the decoder
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    // Get the next frame's payload out of the decoder. Returns `None` if more
+    // data is needed to complete the next frame. Should be called until it
+    // returns `None` after each `push`.
+    pub fn next_frame(&mut self) -> Option<Result<Vec<u8>, FrameError>> {
+        match self.mode {
+            FramingMode::NewlineDelimited => self.next_delimited_frame(),
+            FramingMode::LengthPrefixed => self.next_length_prefixed_frame()
+        }
+    }
+
+    fn next_delimited_frame(&mut self) -> Option<Result<Vec<u8>, FrameError>> {
+        loop {
+            let end = match self.buffer.iter().position(|&b| b == b'\n') {
+                Some(e) => e,
+                None => {
+                    // If the line is already too long throw it away now
+                    // rather than buffering it, and keep discarding until the
+                    // newline turns up.
+                    if self.buffer.len() > self.max_frame_len {
+                        let len = self.discarded_len.unwrap_or(0);
+                        self.discarded_len = Some(len + self.buffer.len());
+                        self.buffer.clear();
+                    }
+                    return None
+                }
+            };
+
+            let mut frame: Vec<u8> = self.buffer.drain(..=end).collect();
+            frame.pop();
+
+            // Be tolerant of CRLF line endings
+            if frame.last() == Some(&b'\r') {
+                frame.pop();
+            }
+
+            // End of a line which was already being discarded
+            if let Some(len) = self.discarded_len.take() {
+                return Some(Err(FrameError::Oversize { len: len + frame.len() }))
+            }
+
+            if frame.len() > self.max_frame_len {
+                return Some(Err(FrameError::Oversize { len: frame.len() }))
+            }
+
+            // Skip blank lines, they could be keepalives from a terminal
+            if frame.iter().all(|b| b.is_ascii_whitespace()) {
+                continue
+            }
+
+            return Some(Ok(frame))
+        }
+    }
+
+    fn next_length_prefixed_frame(&mut self) -> Option<Result<Vec<u8>, FrameError>> {
+
+        // Find the start of the next frame
+        let start = self.buffer
+            .windows(SYNC_WORD.len())
+            .position(|w| w == SYNC_WORD);
+
+        match start {
+            Some(0) => (),
+            Some(s) => {
+                self.buffer.drain(..s);
+                return Some(Err(FrameError::Garbage { skipped: s }))
+            },
+            None => {
+                // Keep the last byte in case it's the first half of a sync
+                // word which is split across reads.
+                let keep = match self.buffer.last() {
+                    Some(&b) if b == SYNC_WORD[0] => 1,
+                    _ => 0
+                };
+                let skipped = self.buffer.len() - keep;
+                self.buffer.drain(..skipped);
+
+                return match skipped {
+                    0 => None,
+                    _ => Some(Err(FrameError::Garbage { skipped }))
+                }
+            }
+        }
+
+        if self.buffer.len() < LENGTH_PREFIX_HEADER_LEN {
+            return None
+        }
+
+        let mut len_bytes = [0u8; 4];
+        len_bytes.copy_from_slice(&self.buffer[2..LENGTH_PREFIX_HEADER_LEN]);
+        let len = u32::from_be_bytes(len_bytes) as usize;
+
+        // An oversize length is most likely a corrupted header or a sync word
+        // appearing inside some garbage, so drop the first sync byte and let
+        // the next call search for a new frame start.
+        if len > self.max_frame_len {
+            self.buffer.drain(..1);
+            return Some(Err(FrameError::Oversize { len }))
+        }
+
+        if self.buffer.len() < LENGTH_PREFIX_HEADER_LEN + len {
+            return None
+        }
+
+        let frame = self.buffer[LENGTH_PREFIX_HEADER_LEN..LENGTH_PREFIX_HEADER_LEN + len]
+            .to_vec();
+        self.buffer.drain(..LENGTH_PREFIX_HEADER_LEN + len);
+
+        Some(Ok(frame))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TESTS
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Push the given chunks into the decoder one at a time, collecting every
+    // result it produces along the way.
+    fn decode_chunks(
+        decoder: &mut FrameDecoder,
+        chunks: &[&[u8]]) -> Vec<Result<Vec<u8>, FrameError>> {
+
+        let mut results = vec![];
+
+        for chunk in chunks {
+            decoder.push(chunk);
+            while let Some(r) = decoder.next_frame() {
+                results.push(r);
+            }
+        }
+
+        results
+    }
+
+    fn encode_all(mode: FramingMode, payloads: &[&[u8]]) -> Vec<u8> {
+        payloads.iter()
+            .flat_map(|p| encode_frame(mode, p).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_delimited_byte_at_a_time() {
+        let stream = encode_all(
+            FramingMode::NewlineDelimited, &[b"{\"a\":1}", b"{\"b\":2}"]);
+        let chunks: Vec<&[u8]> = stream.chunks(1).collect();
+
+        let mut decoder = FrameDecoder::new(FramingMode::NewlineDelimited, 64);
+
+        assert_eq!(decode_chunks(&mut decoder, &chunks), vec![
+            Ok(b"{\"a\":1}".to_vec()),
+            Ok(b"{\"b\":2}".to_vec())
+        ]);
+    }
+
+    #[test]
+    fn test_delimited_multiple_per_read_and_crlf() {
+        let mut decoder = FrameDecoder::new(FramingMode::NewlineDelimited, 64);
+
+        assert_eq!(decode_chunks(&mut decoder, &[b"one\r\n\ntwo\nthr", b"ee\n"]),
+            vec![
+                Ok(b"one".to_vec()),
+                Ok(b"two".to_vec()),
+                Ok(b"three".to_vec())
+            ]);
+    }
+
+    #[test]
+    fn test_delimited_oversize_recovery() {
+        let mut decoder = FrameDecoder::new(FramingMode::NewlineDelimited, 8);
+
+        // The long line is split across reads so it gets discarded before
+        // its newline arrives.
+        assert_eq!(
+            decode_chunks(&mut decoder, &[b"ok\n0123456789", b"0123\nok", b"\n"]),
+            vec![
+                Ok(b"ok".to_vec()),
+                Err(FrameError::Oversize { len: 14 }),
+                Ok(b"ok".to_vec())
+            ]);
+    }
+
+    #[test]
+    fn test_length_prefixed_fragmented() {
+        let stream = encode_all(
+            FramingMode::LengthPrefixed, &[b"first", b"", b"second\nline"]);
+
+        // Try every possible split point in the stream
+        for split in 0..stream.len() {
+            let mut decoder = FrameDecoder::new(FramingMode::LengthPrefixed, 64);
+
+            assert_eq!(
+                decode_chunks(&mut decoder, &[&stream[..split], &stream[split..]]),
+                vec![
+                    Ok(b"first".to_vec()),
+                    Ok(vec![]),
+                    Ok(b"second\nline".to_vec())
+                ]);
+        }
+    }
+
+    #[test]
+    fn test_length_prefixed_garbage_recovery() {
+        let mut stream = b"junk".to_vec();
+        stream.extend(encode_all(FramingMode::LengthPrefixed, &[b"good"]));
+        stream.push(SYNC_WORD[0]);
+        stream.extend(b"more junk");
+        stream.extend(encode_all(FramingMode::LengthPrefixed, &[b"also good"]));
+
+        let mut decoder = FrameDecoder::new(FramingMode::LengthPrefixed, 64);
+
+        assert_eq!(decode_chunks(&mut decoder, &[&stream]), vec![
+            Err(FrameError::Garbage { skipped: 4 }),
+            Ok(b"good".to_vec()),
+            Err(FrameError::Garbage { skipped: 10 }),
+            Ok(b"also good".to_vec())
+        ]);
+    }
+
+    #[test]
+    fn test_length_prefixed_oversize_recovery() {
+        let mut stream = SYNC_WORD.to_vec();
+        stream.extend(&1000u32.to_be_bytes());
+        stream.extend(encode_all(FramingMode::LengthPrefixed, &[b"good"]));
+
+        let mut decoder = FrameDecoder::new(FramingMode::LengthPrefixed, 64);
+
+        assert_eq!(decode_chunks(&mut decoder, &[&stream]), vec![
+            Err(FrameError::Oversize { len: 1000 }),
+            Err(FrameError::Garbage { skipped: 5 }),
+            Ok(b"good".to_vec())
+        ]);
+    }
+
+    #[test]
+    fn test_delimited_rejects_embedded_newline() {
+        assert!(encode_frame(FramingMode::NewlineDelimited, b"a\nb").is_err());
+    }
+}
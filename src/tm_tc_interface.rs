@@ -13,133 +13,1602 @@ use serde::{Serialize, Deserialize, de::DeserializeOwned};
 use serde_json;
 use chrono::{DateTime, Utc};
 use std::thread;
-use std::sync::{Arc, mpsc::{channel, Receiver, Sender, TryRecvError}};
+use std::sync::{
+    Arc, Mutex, Condvar, mpsc::{channel, Receiver, Sender, TryRecvError}};
 use std::sync::atomic::{Ordering, AtomicBool};
-use std::fmt::Debug;
+use std::collections::VecDeque;
+use std::env;
+use std::fmt::{self, Debug};
+use std::io::{self, Read, Write, ErrorKind};
+use std::net::{Shutdown, TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+use crate::tm_tc_error::TmTcError;
+use crate::tm_tc_framing::{
+    FramingMode, FrameDecoder, encode_frame, DEFAULT_MAX_FRAME_LEN};
+use crate::tm_tc_types::{RoverMode, ParamSchema};
+use crate::tc_constructor::{TcHeartbeat, TcDisconnect};
+use crate::tm_deconstructor::{
+    TmHeartbeat, TmDisconnectAck, TmTcAck, TcAckStatus, TmRoverMode};
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+// Period of the backend's cyclic activity. The socket read timeout is set to
+// this value so the backend doesn't spin when there's nothing to do.
+const BACKEND_CYCLE_PERIOD: Duration = Duration::from_millis(10);
+
+// Size of the buffer used for each read from the socket
+const SOCKET_READ_BUFFER_SIZE: usize = 4096;
+
+// Maximum number of sent TCs which are remembered while waiting for the
+// rover to acknowledge them. If more are outstanding the oldest is forgotten.
+const MAX_TCS_AWAITING_ACK: usize = 1024;
+
+// Number of recently received TM frame counters remembered in order to spot
+// duplicates. A frame further behind the newest one than this, or a frame
+// counter of 0, is taken to mean the rover has restarted its counter.
+const TM_FRAME_HISTORY_LEN: usize = 256;
 
 // ---------------------------------------------------------------------------
 // AUTOTMTC PACKET
 // ---------------------------------------------------------------------------
 
-// Trait for data that can be used in an AutoTmTc packet
-pub trait TmTcData: Serialize + DeserializeOwned + Debug {
+// Trait for data that can be used in an AutoTmTc packet
+pub trait TmTcData: Serialize + DeserializeOwned + Debug {
+
+    // The Type Id associated with this type of TM/TC - a unique string 
+    // identifying this type of data. Should be unique, generally follow the
+    // structure name.
+    const TYPE_ID: &'static str;
+
+    // Priority TCs jump ahead of any queued TCs, which are discarded, and are
+    // resent until the rover acknowledges them. Only for TCs which must get
+    // through, like an abort.
+    const PRIORITY: bool = false;
+
+    // Critical TCs can cause harm if sent by mistake, like driving the rover,
+    // so the `TcConstructor` won't send them until they've been armed and
+    // then confirmed by the operator.
+    const CRITICAL: bool = false;
+
+    // The parameters of a TC, in the order they should be shown to the
+    // operator. Types with parameters should list them all, since the GUI
+    // builds its TC input fields from them.
+    const PARAMS: &'static [ParamSchema] = &[];
+
+    // Get the Type Id of this piece of data
+    fn type_id(&self) -> String {
+        String::from(Self::TYPE_ID)
+    }
+
+    // Check that the data is within the ranges the rover will accept. Types
+    // with parameters should override this, and it is checked before any TC
+    // is sent.
+    fn validate(&self) -> Result<(), TmTcError> {
+        Ok(())
+    }
+
+    // Check that the TC can be executed while the rover is in `mode`. Types
+    // which only make sense in some modes should override this, and it is
+    // checked by the `TcConstructor` when the rover's mode is known.
+    fn check_mode(&self, _mode: RoverMode) -> Result<(), TmTcError> {
+        Ok(())
+    }
+
+    // Returns true if this TC must be armed and confirmed before it's sent.
+    // Types which are only critical with some parameters should override
+    // this rather than setting `CRITICAL`.
+    fn is_critical(&self) -> bool {
+        Self::CRITICAL
+    }
+
+}
+
+// When the rover should execute a time-tagged TC, as sent in the packet. 
+// The rover holds time-tagged TCs in its on-board schedule until then.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ExecTime {
+    // At a fixed time
+    Absolute { time_utc: DateTime<Utc> },
+
+    // A delay after the rover finishes executing the TC sent with the given
+    // frame counter
+    AfterTc { frame_counter: u32, delay_s: f64 }
+}
+
+// The structure of a TM or TC packet for sending to the AutonomyManager. 
+// Should be sent using JSON.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct AutoTmTc {
+    // Frame count of this packet, starting from 0.
+    frame_counter: Option<u32>,
+
+    // The time at which this packet was sent, as a UTC DateTime object.
+    send_time_utc: Option<DateTime<Utc>>,
+
+    // When a TC should be executed, or None to execute it on receipt. Left
+    // out of the JSON when not set.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exec_time: Option<ExecTime>,
+
+    // The typename of the data stored in this packet
+    data_type_id: String,
+
+    // The data to be sent as a UTF-8 string
+    data: String
+}
+
+impl AutoTmTc {
+
+    // Build a new packet containing the given data. The frame counter and 
+    // send time are left empty to be filled in by the backend when the 
+    // packet is actually transmitted.
+    pub fn from_data<T>(data: &T) -> Result<Self, TmTcError> where T: TmTcData {
+        Ok(AutoTmTc {
+            frame_counter: None,
+            send_time_utc: None,
+            exec_time: None,
+            data_type_id: data.type_id(),
+            data: serde_json::to_string(data)?
+        })
+    }
+
+    // Set the frame counter and send time of this packet, just before it's
+    // sent.
+    pub fn stamp(&mut self, frame_counter: u32, send_time_utc: DateTime<Utc>) {
+        self.frame_counter = Some(frame_counter);
+        self.send_time_utc = Some(send_time_utc);
+    }
+
+    // Get the frame counter of this packet
+    pub fn frame_counter(&self) -> Option<u32> {
+        self.frame_counter
+    }
+
+    // Get the time at which this packet was sent
+    pub fn send_time_utc(&self) -> Option<DateTime<Utc>> {
+        self.send_time_utc
+    }
+
+    // Get the time at which this TC should be executed, if it's time-tagged
+    pub fn exec_time(&self) -> Option<&ExecTime> {
+        self.exec_time.as_ref()
+    }
+
+    // Set or clear the time at which this TC should be executed
+    pub fn set_exec_time(&mut self, exec_time: Option<ExecTime>) {
+        self.exec_time = exec_time;
+    }
+
+    // Get the type id of the data in this packet
+    pub fn data_type_id(&self) -> &str {
+        &self.data_type_id
+    }
+
+    // Check if this packet contains data of type `T`
+    pub fn is<T>(&self) -> bool where T: TmTcData {
+        self.data_type_id == T::TYPE_ID
+    }
+
+    // Parse the data in this packet as a `T`. Returns an error if the packet
+    // holds some other type of data, rather than attempting to parse it.
+    pub fn parse_data<T>(&self) -> Result<T, TmTcError> where T: TmTcData {
+        if !self.is::<T>() {
+            return Err(TmTcError::TypeMismatch {
+                expected: String::from(T::TYPE_ID),
+                found: self.data_type_id.clone()
+            })
+        }
+
+        Ok(serde_json::from_str(&self.data)?)
+    }
+
+    // Construct the packet back from a JSON string
+    pub fn from_json(json_str: &str) -> Result<Self, TmTcError> {
+        Ok(serde_json::from_str(json_str)?)
+    }
+
+    // Serialise the packet into a JSON string
+    pub fn to_json(&self) -> Result<String, TmTcError> {
+        Ok(serde_json::to_string(&self)?)
+    }
+
+}
+
+// ---------------------------------------------------------------------------
+// CONFIGURATION
+// ---------------------------------------------------------------------------
+
+// Configuration of the interface, i.e. where to find the rover's
+// AutonomyManager on the network.
+#[derive(Clone, Debug)]
+pub struct TmTcIfConfig {
+    // Hostname or IP address of the rover
+    pub rover_host: String,
+
+    // Port on which the AutonomyManager is listening for connections
+    pub rover_port: u16,
+
+    // Maximum time to wait for the connection to the rover to be established
+    pub connect_timeout: Duration,
+
+    // Maximum time a write to the rover may block for. If the rover stops
+    // reading the connection is dropped rather than the backend hanging.
+    pub write_timeout: Duration,
+
+    // How packets are delimited on the TCP stream, must match the rover
+    pub framing: FramingMode,
+
+    // Incoming frames longer than this many bytes are discarded
+    pub max_frame_len: usize,
+
+    // Delay before the first attempt to reconnect after the link drops. The
+    // delay doubles after each failed attempt.
+    pub reconnect_delay_min: Duration,
+
+    // Longest delay between reconnection attempts
+    pub reconnect_delay_max: Duration,
+
+    // Maximum number of TCs held while the link is down. When full the oldest
+    // TC is dropped.
+    pub max_buffered_tcs: usize,
+
+    // How long the link is reported as degraded after a bad or missing TM
+    pub degraded_hold_time: Duration,
+
+    // Period at which `TcHeartbeat`s are sent to the rover while connected
+    pub heartbeat_period: Duration,
+
+    // If no `TmHeartbeat` is received from the rover for this long the link
+    // is considered lost
+    pub heartbeat_timeout: Duration,
+
+    // How long `TmTcIf::stop` waits for the rover to acknowledge the
+    // `TcDisconnect`
+    pub disconnect_timeout: Duration,
+
+    // How long to wait for the rover to acknowledge a priority TC before
+    // sending it again
+    pub priority_tc_retry_period: Duration
+}
+
+impl Default for TmTcIfConfig {
+    fn default() -> Self {
+        TmTcIfConfig {
+            rover_host: String::from("127.0.0.1"),
+            rover_port: 5050,
+            connect_timeout: Duration::from_secs(2),
+            write_timeout: Duration::from_secs(2),
+            framing: FramingMode::NewlineDelimited,
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+            reconnect_delay_min: Duration::from_millis(500),
+            reconnect_delay_max: Duration::from_secs(30),
+            max_buffered_tcs: 256,
+            degraded_hold_time: Duration::from_secs(5),
+            heartbeat_period: Duration::from_secs(1),
+            heartbeat_timeout: Duration::from_secs(5),
+            disconnect_timeout: Duration::from_secs(2),
+            priority_tc_retry_period: Duration::from_millis(500)
+        }
+    }
+}
+
+impl TmTcIfConfig {
+
+    // Build a config from the defaults, overridden by any of these
+    // environment variables which are set:
+    //
+    //  - `ROVER_HOST` - hostname or IP address of the rover
+    //  - `ROVER_PORT` - port the AutonomyManager is listening on
+    //  - `ROVER_FRAMING` - framing mode, either `newline` or `length`
+    pub fn from_env() -> Result<Self, String> {
+        let mut config = TmTcIfConfig::default();
+
+        if let Ok(host) = env::var("ROVER_HOST") {
+            config.rover_host = host;
+        }
+
+        if let Ok(port) = env::var("ROVER_PORT") {
+            config.rover_port = match port.parse() {
+                Ok(p) => p,
+                Err(_) => return Err(format!(
+                    "ROVER_PORT must be a port number but is {}", port))
+            };
+        }
+
+        if let Ok(framing) = env::var("ROVER_FRAMING") {
+            config.framing = match framing.as_str() {
+                "newline" => FramingMode::NewlineDelimited,
+                "length" => FramingMode::LengthPrefixed,
+                f => return Err(format!(
+                    "ROVER_FRAMING must be either newline or length but is {}",
+                    f))
+            };
+        }
+
+        Ok(config)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// LINK STATE
+// ---------------------------------------------------------------------------
+
+// The state of the connection to the rover
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LinkState {
+    // Not connected, waiting to attempt a reconnection
+    Disconnected,
+
+    // Attempting to open the connection
+    Connecting,
+
+    // Connected and receiving good TM
+    Connected,
+
+    // Connected, but TMs have recently been lost or corrupted
+    Degraded
+}
+
+impl fmt::Display for LinkState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkState::Disconnected => write!(f, "Disconnected"),
+            LinkState::Connecting => write!(f, "Connecting"),
+            LinkState::Connected => write!(f, "Connected"),
+            LinkState::Degraded => write!(f, "Degraded")
+        }
+    }
+}
+
+// Notifications from the interface about the link, read using
+// `TmTcIf::get_link_event`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LinkEvent {
+    // The link has moved into a new state
+    StateChanged(LinkState),
+
+    // No heartbeat has been received from the rover within the heartbeat
+    // timeout. The link stays degraded until a heartbeat arrives.
+    LinkLost,
+
+    // A heartbeat has been received from the rover after the link was lost
+    LinkRestored
+}
+
+// ---------------------------------------------------------------------------
+// LINK STATISTICS
+// ---------------------------------------------------------------------------
+
+// Statistics on the traffic over the link to the rover, kept up to date by
+// the backend and available to the GUI through `TmTcIf::link_stats`.
+#[derive(Clone, Debug, Default)]
+pub struct LinkStats {
+    // Number of TCs sent to the rover
+    pub tcs_sent: u64,
+
+    // Number of TCs dropped because the buffer filled up while the link was
+    // down
+    pub tcs_dropped: u64,
+
+    // Frame counter given to the most recently sent TC
+    pub last_tc_frame_counter: Option<u32>,
+
+    // Number of TMs received and passed on to the front end
+    pub tms_received: u64,
+
+    // Frame counter of the newest TM received
+    pub last_tm_frame_counter: Option<u32>,
+
+    // Time at which the last TM was received
+    pub last_tm_receive_time_utc: Option<DateTime<Utc>>,
+
+    // Time at which the last heartbeat was received from the rover
+    pub last_tm_heartbeat_time_utc: Option<DateTime<Utc>>,
+
+    // Number of TM frames skipped over by the rover's frame counter which
+    // haven't (yet) turned up
+    pub tm_frames_missed: u64,
+
+    // Number of TMs received with a frame counter that had already been seen.
+    // These are discarded.
+    pub tm_duplicates: u64,
+
+    // Number of TMs which arrived after a newer frame
+    pub tm_out_of_order: u64,
+
+    // Number of times the rover's TM frame counter jumped backwards, most
+    // likely because the AutonomyManager restarted
+    pub tm_counter_resets: u64,
+
+    // Number of TMs received without a frame counter
+    pub tm_unnumbered: u64,
+
+    // Number of incoming frames which couldn't be decoded into a packet
+    pub tm_decode_errors: u64
+}
+
+impl fmt::Display for LinkStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, 
+            "TC sent: {}, TM recv: {}, missed: {}, dup: {}, ooo: {}, bad: {}",
+            self.tcs_sent,
+            self.tms_received,
+            self.tm_frames_missed,
+            self.tm_duplicates,
+            self.tm_out_of_order,
+            self.tm_decode_errors)
+    }
+}
+
+// The result of checking an incoming TM's frame counter against those already
+// received.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TmFrameCheck {
+    // The frame is the next one expected, or the first one received
+    InSequence,
+
+    // The frame is newer than expected, some frames in between are missing
+    Gap(u32),
+
+    // The frame has already been received
+    Duplicate,
+
+    // The frame is older than the newest one received, but hasn't been seen
+    // before
+    OutOfOrder,
+
+    // The frame counter has gone back to an earlier value than we can track
+    Reset
+}
+
+// Keeps track of the frame counters of incoming TMs.
+#[derive(Default)]
+struct TmFrameTracker {
+    // The highest frame counter received so far
+    newest: Option<u32>,
+
+    // Recently received frame counters
+    recent: VecDeque<u32>
+}
+
+impl TmFrameTracker {
+
+    // Check the given frame counter against those already received, and
+    // record it as received. Counters wrap around, so a frame is newer than
+    // the newest one if it's less than half the counter's range ahead of it.
+    fn check(&mut self, frame_counter: u32) -> TmFrameCheck {
+
+        let newest = match self.newest {
+            Some(n) => n,
+            None => {
+                self.record(frame_counter, TmFrameCheck::InSequence);
+                return TmFrameCheck::InSequence
+            }
+        };
+
+        let ahead = frame_counter.wrapping_sub(newest);
+        let behind = newest.wrapping_sub(frame_counter);
+
+        let result = if ahead == 1 {
+            TmFrameCheck::InSequence
+        }
+        else if ahead == 0 {
+            return TmFrameCheck::Duplicate
+        }
+        else if ahead as usize <= TM_FRAME_HISTORY_LEN {
+            TmFrameCheck::Gap(ahead - 1)
+        }
+        // The rover starts counting from 0 when it restarts, so 0 anywhere
+        // but straight after the counter wraps is a restart rather than a
+        // very old duplicate
+        else if frame_counter == 0 {
+            TmFrameCheck::Reset
+        }
+        else if behind as usize <= TM_FRAME_HISTORY_LEN {
+            if self.recent.contains(&frame_counter) {
+                return TmFrameCheck::Duplicate
+            }
+            TmFrameCheck::OutOfOrder
+        }
+        else if ahead < u32::MAX / 2 {
+            TmFrameCheck::Gap(ahead - 1)
+        }
+        else {
+            TmFrameCheck::Reset
+        };
+
+        self.record(frame_counter, result);
+
+        result
+    }
+
+    // Remember a frame counter which isn't a duplicate
+    fn record(&mut self, frame_counter: u32, result: TmFrameCheck) {
+        match result {
+            TmFrameCheck::OutOfOrder => (),
+            TmFrameCheck::Reset => {
+                self.recent.clear();
+                self.newest = Some(frame_counter);
+            },
+            _ => self.newest = Some(frame_counter)
+        }
+
+        if self.recent.len() >= TM_FRAME_HISTORY_LEN {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(frame_counter);
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TC LIFECYCLE
+// ---------------------------------------------------------------------------
+
+// Where a TC has got to on its way to and through the rover
+#[derive(Clone, Debug, PartialEq)]
+pub enum TcStatus {
+    // Waiting in the interface to be sent
+    Queued,
+
+    // Written to the rover, but not yet acknowledged
+    Sent,
+
+    // The rover has accepted the TC and will execute it
+    Accepted,
+
+    // The rover has refused to execute the TC, for the given reason
+    Rejected(String),
+
+    // The rover has finished executing the TC
+    Completed,
+
+    // The rover tried to execute the TC but couldn't, for the given reason
+    Failed(String),
+
+    // The TC was never sent to the rover, or the link was lost before the
+    // rover acknowledged it, for the given reason
+    Undelivered(String)
+}
+
+impl TcStatus {
+
+    // Returns true if the TC won't progress any further
+    pub fn is_final(&self) -> bool {
+        !matches!(self, TcStatus::Queued | TcStatus::Sent | TcStatus::Accepted)
+    }
+
+    // Returns true if the rover has responded to the TC, or it was never sent
+    pub fn is_acknowledged(&self) -> bool {
+        !matches!(self, TcStatus::Queued | TcStatus::Sent)
+    }
+}
+
+impl fmt::Display for TcStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TcStatus::Queued => write!(f, "Queued"),
+            TcStatus::Sent => write!(f, "Sent"),
+            TcStatus::Accepted => write!(f, "Accepted"),
+            TcStatus::Rejected(r) => write!(f, "Rejected: {}", r),
+            TcStatus::Completed => write!(f, "Completed"),
+            TcStatus::Failed(r) => write!(f, "Failed: {}", r),
+            TcStatus::Undelivered(r) => write!(f, "Undelivered: {}", r)
+        }
+    }
+}
+
+// The state of a TC shared between its handle and the backend
+struct TcTracker {
+    status: TcStatus,
+
+    // Every frame counter the TC has been sent with, oldest first. A resent
+    // priority TC may be acknowledged under any of them.
+    frame_counters: Vec<u32>,
+
+    send_time_utc: Option<DateTime<Utc>>
+}
+
+// A handle on a TC which has been given to the interface, used to find out
+// if the rover has accepted and executed it. Returned from `add_pending_tc`.
+#[derive(Clone)]
+pub struct TcHandle {
+    // The type of data in the TC
+    data_type_id: String,
+
+    // The TC's state, with a condvar notified whenever it changes
+    tracker: Arc<(Mutex<TcTracker>, Condvar)>
+}
+
+impl TcHandle {
+
+    fn new(data_type_id: String) -> Self {
+        TcHandle {
+            data_type_id,
+            tracker: Arc::new((
+                Mutex::new(TcTracker {
+                    status: TcStatus::Queued,
+                    frame_counters: vec![],
+                    send_time_utc: None
+                }),
+                Condvar::new()))
+        }
+    }
+
+    // Get the type id of the data in the TC
+    pub fn data_type_id(&self) -> &str {
+        &self.data_type_id
+    }
+
+    // Get the current status of the TC
+    pub fn status(&self) -> TcStatus {
+        self.tracker.0.lock().unwrap().status.clone()
+    }
+
+    // Get the frame counter the TC was last sent with, or None if it hasn't
+    // been sent yet
+    pub fn frame_counter(&self) -> Option<u32> {
+        self.tracker.0.lock().unwrap().frame_counters.last().copied()
+    }
+
+    // Returns true if the TC has ever been sent with `frame_counter`
+    fn was_sent_with(&self, frame_counter: u32) -> bool {
+        self.tracker.0.lock().unwrap().frame_counters.contains(&frame_counter)
+    }
+
+    // Get the time the TC was last sent, or None if it hasn't been sent yet
+    pub fn send_time_utc(&self) -> Option<DateTime<Utc>> {
+        self.tracker.0.lock().unwrap().send_time_utc
+    }
+
+    // Wait for the rover to accept or reject the TC. Returns the status at
+    // that point, or a timeout error if it didn't happen in time.
+    pub fn wait_for_ack(&self, timeout: Duration) -> Result<TcStatus, TmTcError> {
+        self.wait_until(timeout, TcStatus::is_acknowledged)
+    }
+
+    // Wait for the TC to reach a final status, i.e. be completed, rejected, 
+    // fail, or never be delivered. Returns a timeout error if this doesn't
+    // happen in time.
+    pub fn wait(&self, timeout: Duration) -> Result<TcStatus, TmTcError> {
+        self.wait_until(timeout, TcStatus::is_final)
+    }
+
+    fn wait_until(
+        &self, 
+        timeout: Duration, 
+        done: fn(&TcStatus) -> bool) -> Result<TcStatus, TmTcError> {
+
+        let (lock, cvar) = &*self.tracker;
+        let deadline = Instant::now() + timeout;
+        let mut tracker = lock.lock().unwrap();
+
+        while !done(&tracker.status) {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(TmTcError::Timeout(format!(
+                    "{} TC still {} after {:.1} s", 
+                    self.data_type_id, tracker.status, timeout.as_secs_f64())))
+            }
+
+            tracker = cvar.wait_timeout(tracker, deadline - now).unwrap().0;
+        }
+
+        Ok(tracker.status.clone())
+    }
+
+    // Record that the TC has been sent. A resend can be queued before the
+    // rover's acknowledgement arrives, so a TC which has already been 
+    // acknowledged keeps its status.
+    fn set_sent(&self, frame_counter: u32, send_time_utc: DateTime<Utc>) {
+        let mut tracker = self.tracker.0.lock().unwrap();
+        tracker.frame_counters.push(frame_counter);
+        tracker.send_time_utc = Some(send_time_utc);
+        if !tracker.status.is_acknowledged() {
+            tracker.status = TcStatus::Sent;
+        }
+        self.tracker.1.notify_all();
+    }
+
+    // Returns true if both handles refer to the same TC
+    fn is_same_tc(&self, other: &TcHandle) -> bool {
+        Arc::ptr_eq(&self.tracker, &other.tracker)
+    }
+
+    // Move the TC on to a new status. Once the TC has reached a final status
+    // it isn't changed, in case acknowledgements arrive out of order.
+    fn set_status(&self, status: TcStatus) {
+        let mut tracker = self.tracker.0.lock().unwrap();
+        if !tracker.status.is_final() {
+            tracker.status = status;
+            self.tracker.1.notify_all();
+        }
+    }
+}
+
+impl Debug for TcHandle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tracker = self.tracker.0.lock().unwrap();
+        f.debug_struct("TcHandle")
+            .field("data_type_id", &self.data_type_id)
+            .field("frame_counters", &tracker.frame_counters)
+            .field("status", &tracker.status)
+            .finish()
+    }
+}
+
+// When a scheduled TC should be executed, given when the TC is added to the
+// interface.
+#[derive(Clone, Debug)]
+pub enum TcExecTime {
+    // At a fixed time, which must be in the future
+    At(DateTime<Utc>),
+
+    // A delay after the rover finishes executing an earlier TC
+    After(TcHandle, Duration)
+}
+
+// A TC waiting in the backend to be sent, along with the handle used to
+// report its progress if anyone's interested in it.
+#[derive(Clone)]
+struct PendingTc {
+    packet: AutoTmTc,
+    handle: Option<TcHandle>,
+    priority: bool,
+
+    // For TCs scheduled relative to an earlier TC, which TC and the delay
+    // after it. The earlier TC's frame counter isn't known until it's sent,
+    // so this is only resolved into an `ExecTime` just before sending.
+    exec_after: Option<(TcHandle, Duration)>
+}
+
+// ---------------------------------------------------------------------------
+// STOP REPORT
+// ---------------------------------------------------------------------------
+
+// A summary of a TC passed through the interface
+#[derive(Clone, Debug)]
+pub struct TcRecord {
+    // Frame counter the TC was sent with, if it was sent
+    pub frame_counter: Option<u32>,
+
+    // Time the TC was sent, if it was sent
+    pub send_time_utc: Option<DateTime<Utc>>,
+
+    // The type of data in the TC
+    pub data_type_id: String
+}
+
+impl From<&AutoTmTc> for TcRecord {
+    fn from(tc: &AutoTmTc) -> Self {
+        TcRecord {
+            frame_counter: tc.frame_counter,
+            send_time_utc: tc.send_time_utc,
+            data_type_id: tc.data_type_id.clone()
+        }
+    }
+}
+
+// What happened to the outstanding TCs when the interface was stopped,
+// returned by `TmTcIf::stop`.
+#[derive(Clone, Debug, Default)]
+pub struct StopReport {
+    // TCs which were flushed to the rover during the stop, including the
+    // `TcDisconnect` if it was sent
+    pub delivered: Vec<TcRecord>,
+
+    // TCs which were still waiting to be sent when the interface stopped
+    pub undelivered: Vec<TcRecord>,
+
+    // True if the `TcDisconnect` was sent to the rover
+    pub disconnect_sent: bool,
+
+    // True if the rover acknowledged the `TcDisconnect` before the timeout
+    pub disconnect_acknowledged: bool
+}
+
+// ---------------------------------------------------------------------------
+// TMTCIF MODULE
+// ---------------------------------------------------------------------------
+
+struct TmTcIfBackend {
+
+    // Configuration of the interface, kept for reconnecting
+    config: TmTcIfConfig,
+
+    // The TCP connection to the rover's AutonomyManager, or `None` if the
+    // link is currently down
+    stream: Option<TcpStream>,
+
+    // Decoder splitting the received byte stream into packets
+    decoder: FrameDecoder,
+
+    // The current state of the link, shared with the front end
+    link_state: Arc<Mutex<LinkState>>,
+
+    // Sender for link events (i.e. how the GUI finds out the link state has
+    // changed)
+    event_tx: Sender<LinkEvent>,
+
+    // Delay to wait before the next connection attempt, doubled on each
+    // failure up to the configured maximum
+    reconnect_delay: Duration,
+
+    // Time at which the next connection attempt may be made
+    next_connect_attempt: Instant,
+
+    // Time at which the last problem on the link (bad or missing TMs) was
+    // seen, used to decide if the link is degraded
+    last_link_problem: Option<Instant>,
+
+    // Time at which the last `TcHeartbeat` was sent to the rover
+    last_tc_heartbeat: Option<Instant>,
+
+    // Time at which the last `TmHeartbeat` was received from the rover, or
+    // the connection was made if there hasn't been one since
+    last_tm_heartbeat: Instant,
+
+    // True if the heartbeat watchdog has fired and no heartbeat has been 
+    // received since
+    heartbeat_lost: bool,
+
+    // Queue of pending TCs to be sent in the next send cycle. While the link
+    // is down TCs are buffered here.
+    tc_queue: VecDeque<PendingTc>,
+
+    // Handles of TCs which have been sent and are waiting for the rover to
+    // acknowledge them
+    tcs_awaiting_ack: VecDeque<TcHandle>,
+
+    // Priority TCs which have been sent but not yet acknowledged, along with
+    // the time they were last sent, so they can be resent if need be
+    priority_tcs_unacked: Vec<(PendingTc, Instant)>,
+
+    // Receiver for the TC queue (i.e. how data gets into the interface from 
+    // the GUI)
+    tc_rx: Receiver<PendingTc>,
+
+    // Vector of pending TMs
+    tm_queue: Vec<AutoTmTc>,
+
+    // Sender for the TM queue (i.e. how data gets from the interface to the 
+    // GUI)
+    tm_tx: Sender<AutoTmTc>,
+
+    // Frame counter to give to the next TC sent
+    next_tc_frame_counter: u32,
+
+    // Frame counter tracking for incoming TMs
+    tm_frame_tracker: TmFrameTracker,
+
+    // Statistics on the link, shared with the front end
+    link_stats: Arc<Mutex<LinkStats>>,
+
+    // The rover's mode as last reported in telemetry, shared with the front
+    // end. None if the rover hasn't reported its mode since connecting.
+    rover_mode: Arc<Mutex<Option<RoverMode>>>,
+
+    // Keep running bool
+    run: Arc<AtomicBool>
+}
+
+impl TmTcIfBackend {
+
+    // All the shared state has to be handed to the backend when it starts
+    #[allow(clippy::too_many_arguments)]
+    fn start(
+        config: TmTcIfConfig,
+        chan_tc_rx: Receiver<PendingTc>, 
+        chan_tm_tx: Sender<AutoTmTc>,
+        chan_event_tx: Sender<LinkEvent>,
+        link_state: Arc<Mutex<LinkState>>,
+        link_stats: Arc<Mutex<LinkStats>>,
+        rover_mode: Arc<Mutex<Option<RoverMode>>>,
+        backround_run: Arc<AtomicBool>) -> thread::JoinHandle<StopReport> {
+
+        let mut backend = TmTcIfBackend {
+            decoder: FrameDecoder::new(config.framing, config.max_frame_len),
+            reconnect_delay: config.reconnect_delay_min,
+            config,
+            stream: None,
+            link_state,
+            event_tx: chan_event_tx,
+            next_connect_attempt: Instant::now(),
+            last_link_problem: None,
+            last_tc_heartbeat: None,
+            last_tm_heartbeat: Instant::now(),
+            heartbeat_lost: false,
+            tc_queue: VecDeque::new(),
+            tcs_awaiting_ack: VecDeque::new(),
+            priority_tcs_unacked: vec![],
+            tc_rx: chan_tc_rx,
+            tm_queue: vec![],
+            tm_tx: chan_tm_tx,
+            next_tc_frame_counter: 0,
+            tm_frame_tracker: TmFrameTracker::default(),
+            link_stats,
+            rover_mode,
+            run: backround_run
+        };
+
+        thread::spawn(move || {
+            loop {
+                // Run the cyclic activity
+                match backend.cyclic_activity() {
+                    None => (),
+                    Some(e) => {
+                        eprintln!("TmTcIfBackend exiting due to error: {}", e);
+                        break
+                    }
+                }
+
+                // If the backend has been requested to stop
+                if !(backend.run.load(Ordering::SeqCst)) {
+                    println!("TmTcIfBackend exiting on stop request");
+                    break;
+                }
+            }
+
+            backend.shutdown()
+        })
+    }
+
+    // Open the TCP connection to the rover, trying each address the
+    // configured host resolves to in turn.
+    fn connect(config: &TmTcIfConfig) -> Result<TcpStream, TmTcError> {
+        
+        let addrs = (config.rover_host.as_str(), config.rover_port)
+            .to_socket_addrs()?;
+
+        let mut last_err = io::Error::new(
+            ErrorKind::NotFound, 
+            format!(
+                "No addresses found for {}:{}", 
+                config.rover_host, config.rover_port));
+
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, config.connect_timeout) {
+                Ok(stream) => {
+                    // The read timeout paces the backend's cyclic activity
+                    stream.set_read_timeout(Some(BACKEND_CYCLE_PERIOD))?;
+                    stream.set_write_timeout(Some(config.write_timeout))?;
+
+                    // TCs are small and latency matters more than throughput
+                    stream.set_nodelay(true)?;
+
+                    println!("TmTcIfBackend connected to rover at {}", addr);
+                    return Ok(stream)
+                },
+                Err(e) => {
+                    last_err = io::Error::new(
+                        e.kind(), 
+                        format!("Cannot connect to rover at {}: {}", addr, e))
+                }
+            }
+        }
+
+        Err(TmTcError::Io(last_err))
+    }
+
+    // Check for new TCs coming from the GUI and new TMs coming from the Rover.
+    // Problems with the link itself are handled here by reconnecting, an error
+    // is only returned if the backend cannot carry on at all.
+    fn cyclic_activity(&mut self) -> Option<TmTcError> {
+        
+        // Check for new TCs by reading from the TC receiver
+        for tc in self.tc_rx.try_iter().collect::<Vec<_>>() {
+            self.buffer_tc(tc);
+        }
+
+        // If the link is down try to bring it back up, otherwise wait until
+        // it's time for the next attempt.
+        if self.stream.is_none() {
+            if Instant::now() < self.next_connect_attempt {
+                thread::sleep(BACKEND_CYCLE_PERIOD);
+                return None
+            }
+
+            self.set_link_state(LinkState::Connecting);
+
+            match TmTcIfBackend::connect(&self.config) {
+                Ok(s) => {
+                    self.stream = Some(s);
+                    self.reconnect_delay = self.config.reconnect_delay_min;
+                    self.last_link_problem = None;
+                    self.last_tc_heartbeat = None;
+                    self.last_tm_heartbeat = Instant::now();
+                    self.heartbeat_lost = false;
+
+                    // The rover may have restarted while the link was down,
+                    // in which case its TM frame counter starts again from 0
+                    self.tm_frame_tracker = TmFrameTracker::default();
+
+                    self.set_link_state(LinkState::Connected);
+                },
+                Err(e) => {
+                    eprintln!(
+                        "{}, retrying in {:.1} s", 
+                        e, self.reconnect_delay.as_secs_f64());
+                    self.next_connect_attempt = 
+                        Instant::now() + self.reconnect_delay;
+                    self.reconnect_delay = std::cmp::min(
+                        self.reconnect_delay * 2, 
+                        self.config.reconnect_delay_max);
+                    self.set_link_state(LinkState::Disconnected);
+                    return None
+                }
+            }
+        }
+
+        // Exchange data with the rover, dropping the connection if anything
+        // goes wrong with the socket.
+        if let Err(e) = self.exchange() {
+            eprintln!("Link to rover lost: {}", e);
+            self.drop_connection();
+            return None
+        }
+
+        // Resend any priority TCs the rover hasn't acknowledged in time
+        self.retry_priority_tcs();
+
+        // Pass received TMs on to the front end
+        for tm in self.tm_queue.drain(..) {
+            if self.tm_tx.send(tm).is_err() {
+                return Some(TmTcError::ChannelClosed)
+            }
+        }
+
+        // Check the rover is still sending heartbeats
+        if !self.heartbeat_lost 
+            && self.last_tm_heartbeat.elapsed() > self.config.heartbeat_timeout 
+        {
+            eprintln!(
+                "No heartbeat from rover for {:.1} s, link lost",
+                self.last_tm_heartbeat.elapsed().as_secs_f64());
+            self.heartbeat_lost = true;
+            self.event_tx.send(LinkEvent::LinkLost).ok();
+        }
+
+        // Work out if the link is healthy or not
+        let degraded = self.heartbeat_lost || match self.last_link_problem {
+            Some(t) => t.elapsed() < self.config.degraded_hold_time,
+            None => false
+        };
+        self.set_link_state(match degraded {
+            true => LinkState::Degraded,
+            false => LinkState::Connected
+        });
+
+        None
+    }
+
+    // Send a heartbeat if one is due, then all pending TCs, and read any
+    // waiting TMs. Returns an error if the socket has failed.
+    fn exchange(&mut self) -> Result<(), TmTcError> {
+        self.send_heartbeat()?;
+        self.send_pending_tcs(&mut vec![])?;
+        self.receive_tms()
+    }
+
+    // Write a `TcHeartbeat` straight to the rover if one is due. Heartbeats
+    // only mean anything while the link is up, so unlike other TCs they're
+    // never buffered, and can't push operator TCs out of a full buffer.
+    fn send_heartbeat(&mut self) -> Result<(), TmTcError> {
+        let heartbeat_due = match self.last_tc_heartbeat {
+            Some(t) => t.elapsed() >= self.config.heartbeat_period,
+            None => true
+        };
+
+        let framing = self.config.framing;
+        let stream = match self.stream.as_mut() {
+            Some(s) if heartbeat_due => s,
+            _ => return Ok(())
+        };
+
+        self.last_tc_heartbeat = Some(Instant::now());
+
+        let frame_counter = self.next_tc_frame_counter;
+        let frame = AutoTmTc::from_data(&TcHeartbeat::new())
+            .and_then(|mut tc| {
+                tc.stamp(frame_counter, Utc::now());
+                tc.to_json()
+            })
+            .and_then(|s| encode_frame(framing, s.as_bytes()));
+
+        match frame {
+            Ok(f) => stream.write_all(&f)?,
+            Err(e) => {
+                eprintln!("Cannot build heartbeat: {}", e);
+                return Ok(())
+            }
+        }
+
+        let mut stats = self.link_stats.lock().unwrap();
+        stats.tcs_sent += 1;
+        stats.last_tc_frame_counter = Some(frame_counter);
+
+        self.next_tc_frame_counter = frame_counter.wrapping_add(1);
+
+        Ok(())
+    }
+
+    // Send all pending TCs to the rover, adding a record of each one sent to
+    // `sent`. A TC is only removed from the queue once it's been written so
+    // that it'll be resent if the link fails part way through.
+    fn send_pending_tcs(
+        &mut self, sent: &mut Vec<TcRecord>) -> Result<(), TmTcError> {
+
+        let framing = self.config.framing;
+        let stream = match self.stream.as_mut() {
+            Some(s) => s,
+            None => return Err(TmTcError::Io(
+                io::Error::from(ErrorKind::NotConnected)))
+        };
+
+        while let Some(tc) = self.tc_queue.front_mut() {
+            // The TC this one is scheduled after was sent before it, so its
+            // frame counter is known by now unless it was never sent at all.
+            if let Some((after, delay)) = &tc.exec_after {
+                match after.frame_counter() {
+                    Some(f) => tc.packet.exec_time = Some(ExecTime::AfterTc {
+                        frame_counter: f,
+                        delay_s: delay.as_secs_f64()
+                    }),
+                    None => {
+                        eprintln!(
+                            "Dropping TC {}: the {} TC it is scheduled after \
+                            was never sent", 
+                            tc.packet.data_type_id, after.data_type_id());
+                        if let Some(h) = &tc.handle {
+                            h.set_status(TcStatus::Undelivered(format!(
+                                "Scheduled after a {} TC which was never sent",
+                                after.data_type_id())));
+                        }
+                        self.tc_queue.pop_front();
+                        continue
+                    }
+                }
+            }
+
+            // Stamp the packet with its frame counter and send time
+            let frame_counter = self.next_tc_frame_counter;
+            let send_time_utc = Utc::now();
+            tc.packet.stamp(frame_counter, send_time_utc);
+
+            let frame = match tc.packet.to_json()
+                .and_then(|s| encode_frame(framing, s.as_bytes())) 
+            {
+                Ok(f) => f,
+                Err(e) => {
+                    eprintln!("Dropping TC {}: {}", tc.packet.data_type_id, e);
+                    if let Some(h) = &tc.handle {
+                        h.set_status(TcStatus::Undelivered(format!("{}", e)));
+                    }
+                    self.tc_queue.pop_front();
+                    continue
+                }
+            };
+
+            stream.write_all(&frame)?;
+
+            let mut stats = self.link_stats.lock().unwrap();
+            stats.tcs_sent += 1;
+            stats.last_tc_frame_counter = Some(frame_counter);
+
+            // Keep hold of the handle until the rover acknowledges the TC
+            if let Some(h) = &tc.handle {
+                h.set_sent(frame_counter, send_time_utc);
+
+                // A resent priority TC is already being tracked, and may have
+                // been finished with since the resend was queued
+                self.tcs_awaiting_ack.retain(|a| !a.is_same_tc(h));
+
+                if !h.status().is_final() {
+                    if self.tcs_awaiting_ack.len() >= MAX_TCS_AWAITING_ACK {
+                        self.tcs_awaiting_ack.pop_front();
+                    }
+                    self.tcs_awaiting_ack.push_back(h.clone());
+                }
+            }
+
+            // Priority TCs are kept until they're acknowledged in case they
+            // need resending
+            if tc.priority && tc.handle.is_some() {
+                self.priority_tcs_unacked.push((tc.clone(), Instant::now()));
+            }
+
+            self.next_tc_frame_counter = self.next_tc_frame_counter
+                .wrapping_add(1);
+            sent.push(TcRecord::from(&tc.packet));
+            self.tc_queue.pop_front();
+        }
+
+        Ok(())
+    }
+
+    // Read any new data from the rover and decode it into the TM queue. This
+    // will block for up to the cycle period if there's nothing available.
+    fn receive_tms(&mut self) -> Result<(), TmTcError> {
+
+        let stream = match self.stream.as_mut() {
+            Some(s) => s,
+            None => return Err(TmTcError::Io(
+                io::Error::from(ErrorKind::NotConnected)))
+        };
+
+        let mut read_buf = [0u8; SOCKET_READ_BUFFER_SIZE];
+        match stream.read(&mut read_buf) {
+            Ok(0) => return Err(TmTcError::Io(io::Error::new(
+                ErrorKind::ConnectionAborted, "Connection closed by rover"))),
+            Ok(n) => self.decoder.push(&read_buf[..n]),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock 
+                || e.kind() == ErrorKind::TimedOut => (),
+            Err(e) => return Err(TmTcError::Io(e))
+        }
+
+        // Split the received data into packets
+        while let Some(frame) = self.decoder.next_frame() {
+            let tm = match frame {
+                Ok(f) => match String::from_utf8(f) {
+                    Ok(s) => AutoTmTc::from_json(&s),
+                    Err(e) => Err(TmTcError::Protocol(
+                        format!("TM is not valid UTF-8: {}", e)))
+                },
+                Err(e) => Err(TmTcError::from(e))
+            };
+
+            // A bad packet shouldn't take the whole link down, so just report
+            // it and carry on.
+            match tm {
+                Ok(tm) => if self.check_tm_frame(&tm) {
+                    if tm.is::<TmHeartbeat>() {
+                        self.heartbeat_received();
+                    }
+                    if tm.is::<TmTcAck>() {
+                        self.tc_ack_received(&tm);
+                    }
+                    if tm.is::<TmRoverMode>() {
+                        self.rover_mode_received(&tm);
+                    }
+                    self.tm_queue.push(tm)
+                },
+                Err(e) => {
+                    eprintln!("Discarding TM: {}", e);
+                    self.link_stats.lock().unwrap().tm_decode_errors += 1;
+                    self.last_link_problem = Some(Instant::now());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Disconnect from the rover when the backend stops. Any outstanding TCs
+    // are flushed, followed by a `TcDisconnect`, and then the rover's 
+    // acknowledgement is waited for before the socket is closed.
+    fn shutdown(&mut self) -> StopReport {
+        let mut report = StopReport::default();
+
+        // Pick up any TCs added since the last cycle
+        for tc in self.tc_rx.try_iter().collect::<Vec<_>>() {
+            self.buffer_tc(tc);
+        }
+
+        let disconnect_handle = TcHandle::new(String::from(TcDisconnect::TYPE_ID));
+
+        if self.stream.is_some() {
+            match AutoTmTc::from_data(&TcDisconnect::new()) {
+                Ok(tc) => self.tc_queue.push_back(PendingTc {
+                    packet: tc,
+                    handle: Some(disconnect_handle.clone()),
+                    priority: false,
+                    exec_after: None
+                }),
+                Err(e) => eprintln!("Cannot build disconnect TC: {}", e)
+            }
+
+            if let Err(e) = self.send_pending_tcs(&mut report.delivered) {
+                eprintln!("Failed to flush TCs to rover: {}", e);
+            }
+
+            report.disconnect_sent = disconnect_handle.frame_counter().is_some();
+        }
+
+        // Wait for the rover to acknowledge the disconnect, passing on any
+        // other TMs which turn up in the meantime.
+        let ack_deadline = Instant::now() + self.config.disconnect_timeout;
+        while report.disconnect_sent && Instant::now() < ack_deadline {
+            if let Err(e) = self.receive_tms() {
+                eprintln!("Link to rover lost during disconnect: {}", e);
+                break
+            }
+
+            for tm in self.tm_queue.drain(..) {
+                if tm.is::<TmDisconnectAck>() {
+                    report.disconnect_acknowledged = true;
+                }
 
-    // Get the Type Id associated with this type of TM/TC - a unique string 
-    // identifying this type of data. Should be unique, generally follow the
-    // structure name.
-    fn type_id(&self) -> String;
+                // The front end may already be gone, which is fine
+                self.tm_tx.send(tm).ok();
+            }
 
-}
+            // The rover may also acknowledge the disconnect like any other TC
+            match disconnect_handle.status() {
+                TcStatus::Accepted | TcStatus::Completed => {
+                    report.disconnect_acknowledged = true
+                },
+                _ => ()
+            }
 
-// The structure of a TM or TC packet for sending to the AutonomyManager. 
-// Should be sent using JSON.
-#[derive(Serialize, Deserialize, Clone, Debug)]
-struct AutoTmTc {
-    // Frame count of this packet, starting from 0.
-    frame_counter: Option<u32>,
+            if report.disconnect_acknowledged {
+                break
+            }
+        }
 
-    // The time at which this packet was sent, as a UTC DateTime object.
-    send_time_utc: Option<DateTime<Utc>>,
+        if report.disconnect_sent && !report.disconnect_acknowledged {
+            eprintln!("Rover did not acknowledge the disconnect");
+        }
 
-    // The typename of the data stored in this packet
-    data_type_id: String,
+        // Anything still in the queue never made it to the rover
+        for tc in self.tc_queue.drain(..) {
+            if let Some(h) = &tc.handle {
+                h.set_status(TcStatus::Undelivered(String::from(
+                    "Interface stopped before the TC was sent")));
+            }
+            report.undelivered.push(TcRecord::from(&tc.packet));
+        }
 
-    // The data to be sent as a UTF-8 string
-    data: String
-}
+        if let Some(s) = self.stream.take() {
+            s.shutdown(Shutdown::Both).ok();
+        }
+        self.set_link_state(LinkState::Disconnected);
 
-impl AutoTmTc {
+        report
+    }
+
+    // Reset the heartbeat watchdog after a heartbeat is received from the
+    // rover.
+    fn heartbeat_received(&mut self) {
+        self.last_tm_heartbeat = Instant::now();
+        self.link_stats.lock().unwrap().last_tm_heartbeat_time_utc = 
+            Some(Utc::now());
 
-    // Construct the packet back from a JSON string, returns string on error.
-    fn from_json(json_str: &str) -> Result<Self, String> {
-        match serde_json::from_str(json_str) {
-            Ok(a) => Ok(a),
-            Err(e) => Err(format!("Cannot parse JSON string: {}", e))
+        if self.heartbeat_lost {
+            println!("Heartbeat from rover received, link restored");
+            self.heartbeat_lost = false;
+            self.event_tx.send(LinkEvent::LinkRestored).ok();
         }
     }
 
-    // Serialise the packet into a JSON string
-    fn to_json(&self) -> Result<String, String> {
-        match serde_json::to_string(&self) {
-            Ok(s) => Ok(s),
-            Err(e) => Err(format!("Cannot serialise the packet: {}", e))
+    // Record the rover's mode so TCs can be checked against it
+    fn rover_mode_received(&mut self, tm: &AutoTmTc) {
+        match tm.parse_data::<TmRoverMode>() {
+            Ok(m) => *self.rover_mode.lock().unwrap() = Some(m.mode),
+            Err(e) => eprintln!("Cannot parse rover mode: {}", e)
         }
     }
 
-}
+    // Update the status of the TC an acknowledgement from the rover refers
+    // to.
+    fn tc_ack_received(&mut self, tm: &AutoTmTc) {
+        let ack = match tm.parse_data::<TmTcAck>() {
+            Ok(a) => a,
+            Err(e) => {
+                eprintln!("Cannot parse TC acknowledgement: {}", e);
+                return
+            }
+        };
 
-// ---------------------------------------------------------------------------
-// TMTCIF MODULE
-// ---------------------------------------------------------------------------
+        // A resent TC's acks may refer to any of the times it was sent
+        let index = self.tcs_awaiting_ack.iter()
+            .position(|h| h.was_sent_with(ack.frame_counter));
 
-struct TmTcIfBackend {
+        // TCs sent without a handle, like heartbeats, aren't tracked
+        let handle = match index {
+            Some(i) => &self.tcs_awaiting_ack[i],
+            None => return
+        };
 
-    // A vector of pending TCs to be sent in the next send cycle
-    tc_queue: Vec<AutoTmTc>,
+        handle.set_status(match ack.status {
+            TcAckStatus::Accepted => TcStatus::Accepted,
+            TcAckStatus::Rejected { reason } => TcStatus::Rejected(reason),
+            TcAckStatus::Completed => TcStatus::Completed,
+            TcAckStatus::Failed { reason } => TcStatus::Failed(reason)
+        });
 
-    // Receiver for the TC queue (i.e. how data gets into the interface from 
-    // the GUI)
-    tc_rx: Receiver<AutoTmTc>,
+        // Nothing more will happen to the TC so stop tracking it
+        if handle.status().is_final() {
+            if let Some(i) = index {
+                self.tcs_awaiting_ack.remove(i);
+            }
+        }
+    }
 
-    // Vector of pending TMs
-    tm_queue: Vec<AutoTmTc>,
+    // Put any priority TCs which haven't been acknowledged within the retry
+    // period back at the front of the send queue. TCs which have been
+    // acknowledged, or given up on, are forgotten.
+    fn retry_priority_tcs(&mut self) {
+        let retry_period = self.config.priority_tc_retry_period;
+        let mut retries = vec![];
 
-    // Sender for the TM queue (i.e. how data gets from the interface to the 
-    // GUI)
-    tm_tx: Sender<AutoTmTc>,
+        self.priority_tcs_unacked.retain(|(tc, last_sent)| {
+            let unacked = match &tc.handle {
+                Some(h) => !h.status().is_acknowledged(),
+                None => false
+            };
 
-    // Keep running bool
-    run: Arc<AtomicBool>
-}
+            if unacked && last_sent.elapsed() >= retry_period {
+                retries.push(tc.clone());
+                false
+            }
+            else {
+                unacked
+            }
+        });
 
-impl TmTcIfBackend {
+        for tc in retries.into_iter().rev() {
+            eprintln!(
+                "No acknowledgement for priority TC {}, resending", 
+                tc.packet.data_type_id);
+            self.tc_queue.push_front(tc);
+        }
+    }
 
-    fn start(
-        chan_tc_rx: Receiver<AutoTmTc>, 
-        chan_tm_tx: Sender<AutoTmTc>,
-        backround_run: Arc<AtomicBool>) -> thread::JoinHandle<()> {
+    // Add a TC to the send queue, dropping the oldest buffered TC if the
+    // queue is full. A priority TC instead discards every queued TC which
+    // isn't also a priority TC.
+    fn buffer_tc(&mut self, tc: PendingTc) {
+        if tc.priority {
+            let data_type_id = &tc.packet.data_type_id;
+            let mut num_discarded = 0;
 
-        let mut backend = TmTcIfBackend {
-            tc_queue: vec![],
-            tc_rx: chan_tc_rx,
-            tm_queue: vec![],
-            tm_tx: chan_tm_tx,
-            run: backround_run
-        };
+            self.tc_queue.retain(|old| {
+                if old.priority {
+                    return true
+                }
 
-        thread::spawn(move || {
-            loop {
-                // Run the cyclic activity
-                match backend.cyclic_activity() {
-                    None => (),
-                    Some(e) => {
-                        eprintln!("TmTcIfBackend exiting due to error: {}", e);
-                        break
-                    }
+                if let Some(h) = &old.handle {
+                    h.set_status(TcStatus::Undelivered(format!(
+                        "Discarded by priority TC {}", data_type_id)));
                 }
+                num_discarded += 1;
+                false
+            });
 
-                // If the backend has been requested to stop
-                if !(backend.run.load(Ordering::SeqCst)) {
-                    println!("TmTcIfBackend exiting on stop request");
-                    break;
+            if num_discarded > 0 {
+                eprintln!(
+                    "Priority TC {} discarded {} queued TCs", 
+                    data_type_id, num_discarded);
+                self.link_stats.lock().unwrap().tcs_dropped += num_discarded;
+            }
+
+            self.tc_queue.push_back(tc);
+            return
+        }
+
+        if self.tc_queue.len() >= self.config.max_buffered_tcs {
+            if let Some(old) = self.tc_queue.pop_front() {
+                eprintln!(
+                    "TC buffer full, dropping oldest TC {}", 
+                    old.packet.data_type_id);
+                if let Some(h) = &old.handle {
+                    h.set_status(TcStatus::Undelivered(
+                        String::from("TC buffer full")));
                 }
+                self.link_stats.lock().unwrap().tcs_dropped += 1;
             }
+        }
 
-            // TODO: Cleanup
-        })
+        self.tc_queue.push_back(tc);
     }
 
-    // Check for new TCs coming from the GUI and new TMs coming from the Rover
-    fn cyclic_activity(&mut self) -> Option<String> {
-        
-        // Check for new TCs by reading from the TC receiver
-        for tc in self.tc_rx.try_iter() {
-            // For now just print them out 
-            // TODO: send to rover
-            println!("TC: {:?}", tc);
+    // Close the connection to the rover and schedule a reconnection attempt.
+    fn drop_connection(&mut self) {
+        if let Some(s) = self.stream.take() {
+            s.shutdown(Shutdown::Both).ok();
         }
 
-        None
+        // Any partial frame left over from the old connection is useless
+        self.decoder = FrameDecoder::new(
+            self.config.framing, self.config.max_frame_len);
+
+        // The rover's mode may change while the link is down
+        *self.rover_mode.lock().unwrap() = None;
+
+        // TCs written but not yet acknowledged may never have reached the
+        // rover. Priority TCs are resent once the link is back, anything else
+        // is given up on. TCs the rover has accepted are still being
+        // executed, so stay tracked.
+        let resends: Vec<PendingTc> = self.priority_tcs_unacked.drain(..)
+            .map(|(tc, _)| tc)
+            .filter(|tc| match &tc.handle {
+                Some(h) => !h.status().is_acknowledged(),
+                None => false
+            })
+            .collect();
+
+        for tc in resends.into_iter().rev() {
+            self.tc_queue.push_front(tc);
+        }
+
+        let tc_queue = &self.tc_queue;
+        self.tcs_awaiting_ack.retain(|h| {
+            let resending = tc_queue.iter().any(|tc| match &tc.handle {
+                Some(q) => q.is_same_tc(h),
+                None => false
+            });
+
+            if h.status().is_acknowledged() || resending {
+                return true
+            }
+
+            h.set_status(TcStatus::Undelivered(String::from(
+                "Link lost before the rover acknowledged the TC")));
+            false
+        });
+
+        self.next_connect_attempt = Instant::now() + self.reconnect_delay;
+        self.set_link_state(LinkState::Disconnected);
+    }
+
+    // Update the shared link state, notifying the front end if it changed.
+    fn set_link_state(&mut self, new_state: LinkState) {
+        let mut state = self.link_state.lock().unwrap();
+
+        if *state != new_state {
+            *state = new_state;
+            
+            // Nobody listening for events isn't a problem for the backend
+            self.event_tx.send(LinkEvent::StateChanged(new_state)).ok();
+        }
+    }
+
+    // Update the link statistics with a newly received TM, returning false if
+    // the TM is a duplicate and should be discarded.
+    fn check_tm_frame(&mut self, tm: &AutoTmTc) -> bool {
+        let mut stats = self.link_stats.lock().unwrap();
+
+        let frame_counter = match tm.frame_counter {
+            Some(f) => f,
+            None => {
+                stats.tm_unnumbered += 1;
+                stats.tms_received += 1;
+                stats.last_tm_receive_time_utc = Some(Utc::now());
+                return true
+            }
+        };
+
+        let check = self.tm_frame_tracker.check(frame_counter);
+
+        match check {
+            TmFrameCheck::InSequence => (),
+            TmFrameCheck::Gap(n) => stats.tm_frames_missed += n as u64,
+            TmFrameCheck::Duplicate => stats.tm_duplicates += 1,
+            TmFrameCheck::OutOfOrder => {
+                // This frame will have been counted as missed when the gap
+                // before the newer frame was found.
+                stats.tm_out_of_order += 1;
+                stats.tm_frames_missed = stats.tm_frames_missed
+                    .saturating_sub(1);
+            },
+            TmFrameCheck::Reset => stats.tm_counter_resets += 1
+        }
+
+        match check {
+            TmFrameCheck::InSequence => (),
+            _ => self.last_link_problem = Some(Instant::now())
+        }
+
+        if check == TmFrameCheck::Duplicate {
+            return false
+        }
+
+        stats.tms_received += 1;
+        stats.last_tm_receive_time_utc = Some(Utc::now());
+        stats.last_tm_frame_counter = self.tm_frame_tracker.newest;
+
+        true
     }
 
 }
@@ -149,99 +1618,650 @@ pub struct TmTcIf {
 
     // Sender for the TC queue (i.e. how data gets into the interface from the 
     // GUI)
-    tc_tx: Sender<AutoTmTc>,
+    tc_tx: Sender<PendingTc>,
 
     // Receiver for the TM queue (i.e. how data gets from the interface to the 
     // GUI)
     tm_rx: Receiver<AutoTmTc>,
 
     // Handle to the backend thread
-    backend_thread_handle: thread::JoinHandle<()>,
+    backend_thread_handle: thread::JoinHandle<StopReport>,
 
     // Stop atomic bool used to stop the backend when a stop() func is called
-    backend_run: Arc<AtomicBool>
+    backend_run: Arc<AtomicBool>,
+
+    // Receiver for link events from the backend
+    event_rx: Receiver<LinkEvent>,
+
+    // The current state of the link, updated by the backend
+    link_state: Arc<Mutex<LinkState>>,
+
+    // Statistics on the link, updated by the backend
+    link_stats: Arc<Mutex<LinkStats>>,
+
+    // The rover's mode as last reported in telemetry, updated by the backend
+    rover_mode: Arc<Mutex<Option<RoverMode>>>
 
 }
 
 impl TmTcIf {
 
     // Start the interface processing, and return the interface structure that
-    // you can call `add_pending_tc` and `get_pending_tm` on.
-    pub fn start() -> Self {
+    // you can call `add_pending_tc` and `get_pending_tm` on. The connection to
+    // the rover is made in the background, and remade if it drops, so use
+    // `link_state` or `get_link_event` to find out if the rover is reachable.
+    pub fn start(config: TmTcIfConfig) -> Self {
 
         // Create channels
-        let (chan_tc_tx, chan_tc_rx) = channel::<AutoTmTc>();
+        let (chan_tc_tx, chan_tc_rx) = channel::<PendingTc>();
         let (chan_tm_tx, chan_tm_rx) = channel::<AutoTmTc>();
+        let (chan_event_tx, chan_event_rx) = channel::<LinkEvent>();
 
         // Create atomic run bool
         let backend_run_bool = Arc::new(AtomicBool::new(true));
 
+        // Create the shared link state and statistics
+        let link_state = Arc::new(Mutex::new(LinkState::Disconnected));
+        let link_stats = Arc::new(Mutex::new(LinkStats::default()));
+        let rover_mode = Arc::new(Mutex::new(None));
+
         // Start the backend
         let backend_handle = TmTcIfBackend::start(
-            chan_tc_rx, chan_tm_tx, backend_run_bool.clone());
+            config, 
+            chan_tc_rx, 
+            chan_tm_tx, 
+            chan_event_tx,
+            link_state.clone(),
+            link_stats.clone(),
+            rover_mode.clone(),
+            backend_run_bool.clone());
 
         // Create the front end interface
         let tm_tc_if = TmTcIf {
             tc_tx: chan_tc_tx,
             tm_rx: chan_tm_rx,
             backend_thread_handle: backend_handle,
-            backend_run: backend_run_bool
+            backend_run: backend_run_bool,
+            event_rx: chan_event_rx,
+            link_state,
+            link_stats,
+            rover_mode
         };
 
         tm_tc_if
     }
 
     // Stop the execution of the interface, including disconnnecting from the 
-    // rover and sending the disconnect TC
-    pub fn stop(self) -> Result<(), String> {
-
-        // TODO: Disconnect
+    // rover and sending the disconnect TC. Any TCs not yet sent are flushed
+    // first, and the returned report lists which made it to the rover.
+    pub fn stop(self) -> Result<StopReport, TmTcError> {
 
-        // Store a false in the backend run bool
+        // Store a false in the backend run bool, the backend will disconnect
+        // from the rover before its thread exits.
         self.backend_run.store(false, Ordering::SeqCst);
 
         // Wait for the background thread to join
         match self.backend_thread_handle.join() {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("Failed to join backend thread: {:?}", e))
+            Ok(r) => Ok(r),
+            Err(_) => Err(TmTcError::BackendPanicked)
         }
     }
 
+    // Get the current state of the link to the rover
+    pub fn link_state(&self) -> LinkState {
+        *self.link_state.lock().unwrap()
+    }
+
+    // Get the next pending link event, or None if nothing has happened since
+    // the last call
+    pub fn get_link_event(&self) -> Option<LinkEvent> {
+        self.event_rx.try_recv().ok()
+    }
+
+    // Get a snapshot of the current link statistics
+    pub fn link_stats(&self) -> LinkStats {
+        self.link_stats.lock().unwrap().clone()
+    }
+
+    // Get the rover's mode as last reported in telemetry, or None if it 
+    // hasn't been reported since the link was established
+    pub fn rover_mode(&self) -> Option<RoverMode> {
+        *self.rover_mode.lock().unwrap()
+    }
+
     // Add a new piece of data to the TC queue to be sent to the rover's 
-    // AutonomyManager.
-    pub fn add_pending_tc<T>(&mut self, data: T) -> Result<(), String> where 
+    // AutonomyManager. The frame counter and send time are filled in by the
+    // backend when the packet is actually transmitted. The returned handle
+    // can be used to follow the TC through the rover.
+    pub fn add_pending_tc<T>(&mut self, data: T) -> Result<TcHandle, TmTcError> where 
         T: TmTcData {
         
-        // Parse the data to a JSON string
-        let json_str = match serde_json::to_string(&data) {
-            Ok(s) => s,
-            Err(e) => return Err(format!("Failed to serialise data: {}", e))
-        };
+        data.validate()?;
+
+        let handle = TcHandle::new(data.type_id());
 
         // Build the packet and send to the backend
-        match self.tc_tx.send(AutoTmTc {
-            frame_counter: None,
-            send_time_utc: None,
-            data_type_id: data.type_id(),
-            data: json_str
+        match self.tc_tx.send(PendingTc {
+            packet: AutoTmTc::from_data(&data)?,
+            handle: Some(handle.clone()),
+            priority: T::PRIORITY,
+            exec_after: None
         }) {
-            Ok(_) => Ok(()),
-            Err(e) => Err(format!("Failed to add packet to queue: {}", e))
+            Ok(_) => Ok(handle),
+            Err(_) => Err(TmTcError::ChannelClosed)
         }
     }
 
-    // Get a pending TM from the buffer, or return None if none available
-    pub fn get_pending_tm<T>(&self) -> Result<Option<T>, String> where
-        T: TmTcData {
+    // Add a time-tagged TC to the queue, which the rover will hold in its
+    // schedule and execute at `exec_time`. The handle reports the TC as 
+    // accepted once it's scheduled and completed once it's been executed.
+    // Priority TCs must be executed immediately so can't be scheduled.
+    pub fn add_scheduled_tc<T>(&mut self, data: T, exec_time: TcExecTime) 
+        -> Result<TcHandle, TmTcError> where T: TmTcData {
+
+        if T::PRIORITY {
+            return Err(TmTcError::InvalidParameter(format!(
+                "{} is a priority TC and can't be scheduled", T::TYPE_ID)))
+        }
+
+        data.validate()?;
+
+        let mut packet = AutoTmTc::from_data(&data)?;
+        let mut exec_after = None;
+
+        match exec_time {
+            TcExecTime::At(time_utc) => {
+                if time_utc <= Utc::now() {
+                    return Err(TmTcError::InvalidParameter(format!(
+                        "Execution time {} is in the past", time_utc)))
+                }
+                packet.exec_time = Some(ExecTime::Absolute { time_utc });
+            },
+            TcExecTime::After(h, delay) => exec_after = Some((h, delay))
+        }
+
+        let handle = TcHandle::new(data.type_id());
+
+        match self.tc_tx.send(PendingTc {
+            packet,
+            handle: Some(handle.clone()),
+            priority: false,
+            exec_after
+        }) {
+            Ok(_) => Ok(handle),
+            Err(_) => Err(TmTcError::ChannelClosed)
+        }
+    }
+
+    // Get a pending TM packet from the buffer, or return None if none 
+    // available. Use a `TmDeconstructor` to turn the packets back into data.
+    pub fn get_pending_tm(&self) -> Result<Option<AutoTmTc>, TmTcError> {
         
         // Read from the TM reciever
         match self.tm_rx.try_recv() {
-            Ok(tm) => match serde_json::from_str(&tm.data) {
-                Ok(d) => Ok(Some(d)),
-                Err(e) => Err(format!("Failed to parse incoming data: {}", e))
-            },
+            Ok(tm) => Ok(Some(tm)),
             Err(TryRecvError::Empty) => Ok(None),
-            Err(TryRecvError::Disconnected) => Err(format!("Internal channel disconnected"))
+            Err(TryRecvError::Disconnected) => Err(TmTcError::ChannelClosed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feed frame counters through a new tracker, returning what it made of
+    // each one
+    fn track(frame_counters: &[u32]) -> Vec<TmFrameCheck> {
+        let mut tracker = TmFrameTracker::default();
+        frame_counters.iter().map(|f| tracker.check(*f)).collect()
+    }
+
+    // Send TMs to the interface as a rover would, with frame counters from 0
+    fn send_tms<T>(stream: &mut TcpStream, tms: &[T]) where T: TmTcData {
+        for (i, tm) in tms.iter().enumerate() {
+            let mut packet = AutoTmTc::from_data(tm).unwrap();
+            packet.stamp(i as u32, Utc::now());
+            let frame = encode_frame(
+                FramingMode::NewlineDelimited, 
+                packet.to_json().unwrap().as_bytes()).unwrap();
+            stream.write_all(&frame).unwrap();
+        }
+    }
+
+    // Wait for `done` to be true, failing the test if it takes too long
+    fn wait_for<F>(what: &str, done: F) where F: Fn() -> bool {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while !done() {
+            assert!(Instant::now() < deadline, "Timed out waiting for {}", what);
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_frames_in_sequence() {
+        assert_eq!(track(&[5, 6, 7]), vec![TmFrameCheck::InSequence; 3]);
+    }
+
+    #[test]
+    fn test_frame_gaps() {
+        assert_eq!(track(&[0, 1, 4, 5, 1000]), vec![
+            TmFrameCheck::InSequence,
+            TmFrameCheck::InSequence,
+            TmFrameCheck::Gap(2),
+            TmFrameCheck::InSequence,
+            TmFrameCheck::Gap(994)
+        ]);
+    }
+
+    #[test]
+    fn test_frame_duplicates_and_out_of_order() {
+        assert_eq!(track(&[10, 11, 14, 14, 11, 12, 12, 15]), vec![
+            TmFrameCheck::InSequence,
+            TmFrameCheck::InSequence,
+            TmFrameCheck::Gap(2),
+            TmFrameCheck::Duplicate,
+            TmFrameCheck::Duplicate,
+            TmFrameCheck::OutOfOrder,
+            TmFrameCheck::Duplicate,
+            TmFrameCheck::InSequence
+        ]);
+    }
+
+    #[test]
+    fn test_frame_counter_wraparound() {
+        assert_eq!(track(&[u32::MAX - 1, u32::MAX, 0, 1]), 
+            vec![TmFrameCheck::InSequence; 4]);
+
+        // A gap across the wrap is still a gap, and frames from before it
+        // are still recognised
+        assert_eq!(track(&[u32::MAX - 1, 2, u32::MAX, u32::MAX - 1]), vec![
+            TmFrameCheck::InSequence,
+            TmFrameCheck::Gap(3),
+            TmFrameCheck::OutOfOrder,
+            TmFrameCheck::Duplicate
+        ]);
+    }
+
+    #[test]
+    fn test_frame_counter_restart() {
+        // The rover restarting its counter shortly after it started, when 0
+        // is still in the history, mustn't be taken as a duplicate
+        assert_eq!(track(&[0, 1, 2, 3, 0, 1, 2]), vec![
+            TmFrameCheck::InSequence,
+            TmFrameCheck::InSequence,
+            TmFrameCheck::InSequence,
+            TmFrameCheck::InSequence,
+            TmFrameCheck::Reset,
+            TmFrameCheck::InSequence,
+            TmFrameCheck::InSequence
+        ]);
+
+        // Nor restarting after a gap, or jumping a long way backwards
+        assert_eq!(track(&[40, 41, 0, 1]), vec![
+            TmFrameCheck::InSequence,
+            TmFrameCheck::InSequence,
+            TmFrameCheck::Reset,
+            TmFrameCheck::InSequence
+        ]);
+        assert_eq!(track(&[5000, 5001, 7, 8]), vec![
+            TmFrameCheck::InSequence,
+            TmFrameCheck::InSequence,
+            TmFrameCheck::Reset,
+            TmFrameCheck::InSequence
+        ]);
+    }
+
+    #[test]
+    fn test_reconnect_to_restarted_rover() {
+        const NUM_TMS: usize = 20;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (done_tx, done_rx) = channel::<()>();
+
+        // A rover which sends some TMs, restarts, and sends the same frame
+        // counters again, staying connected until the test is done
+        let rover = thread::spawn(move || {
+            let tms: Vec<TmRoverMode> = (0..NUM_TMS)
+                .map(|_| TmRoverMode { mode: RoverMode::Standby })
+                .collect();
+
+            let (mut first, _) = listener.accept().unwrap();
+            send_tms(&mut first, &tms);
+            first.shutdown(Shutdown::Both).ok();
+
+            let (mut second, _) = listener.accept().unwrap();
+            send_tms(&mut second, &tms);
+            done_rx.recv().ok();
+        });
+
+        let tm_tc_if = TmTcIf::start(TmTcIfConfig {
+            rover_port: port,
+            reconnect_delay_min: Duration::from_millis(10),
+            ..TmTcIfConfig::default()
+        });
+
+        wait_for("TMs from both connections", || {
+            let stats = tm_tc_if.link_stats();
+            stats.tms_received + stats.tm_duplicates >= 2 * NUM_TMS as u64
+        });
+
+        // Nothing from after the restart is lost or seen as a problem
+        let stats = tm_tc_if.link_stats();
+        assert_eq!(stats.tms_received, 2 * NUM_TMS as u64);
+        assert_eq!(stats.tm_duplicates, 0);
+        assert_eq!(stats.tm_counter_resets, 0);
+        assert_eq!(tm_tc_if.rover_mode(), Some(RoverMode::Standby));
+        assert_eq!(tm_tc_if.link_state(), LinkState::Connected);
+
+        done_tx.send(()).ok();
+        tm_tc_if.stop().unwrap();
+        rover.join().unwrap();
+    }
+
+    #[test]
+    fn test_link_lost_before_ack() {
+        let mut backend = test_backend(TmTcIfConfig::default());
+        let _rover = connect_backend(&mut backend);
+        let mut sent = vec![];
+
+        let (normal, normal_handle) = pending_tc(false);
+        let (accepted, accepted_handle) = pending_tc(false);
+        let (abort, abort_handle) = pending_tc(true);
+        backend.buffer_tc(normal);
+        backend.buffer_tc(accepted);
+        backend.send_pending_tcs(&mut sent).unwrap();
+        backend.buffer_tc(abort);
+        backend.send_pending_tcs(&mut sent).unwrap();
+        ack(&mut backend, 1, TcAckStatus::Accepted);
+
+        backend.drop_connection();
+
+        // The unacknowledged TC is given up on rather than left waiting
+        assert!(matches!(normal_handle.status(), TcStatus::Undelivered(_)));
+        assert_eq!(
+            normal_handle.wait(Duration::from_millis(20)).unwrap(),
+            normal_handle.status());
+
+        // The accepted TC may still be completed
+        assert_eq!(accepted_handle.status(), TcStatus::Accepted);
+
+        // and the abort is resent on reconnection
+        assert_eq!(backend.tc_queue.len(), 1);
+        assert!(backend.tc_queue[0].handle.as_ref().unwrap()
+            .is_same_tc(&abort_handle));
+        assert_eq!(backend.tcs_awaiting_ack.len(), 2);
+    }
+
+    // A backend which isn't connected to anything, for passing TCs and TMs
+    // to directly
+    fn test_backend(config: TmTcIfConfig) -> TmTcIfBackend {
+        let (_, tc_rx) = channel();
+        let (tm_tx, _) = channel();
+        let (event_tx, _) = channel();
+
+        TmTcIfBackend {
+            decoder: FrameDecoder::new(config.framing, config.max_frame_len),
+            reconnect_delay: config.reconnect_delay_min,
+            config,
+            stream: None,
+            link_state: Arc::new(Mutex::new(LinkState::Disconnected)),
+            event_tx,
+            next_connect_attempt: Instant::now(),
+            last_link_problem: None,
+            last_tc_heartbeat: None,
+            last_tm_heartbeat: Instant::now(),
+            heartbeat_lost: false,
+            tc_queue: VecDeque::new(),
+            tcs_awaiting_ack: VecDeque::new(),
+            priority_tcs_unacked: vec![],
+            tc_rx,
+            tm_queue: vec![],
+            tm_tx,
+            next_tc_frame_counter: 0,
+            tm_frame_tracker: TmFrameTracker::default(),
+            link_stats: Arc::new(Mutex::new(LinkStats::default())),
+            rover_mode: Arc::new(Mutex::new(None)),
+            run: Arc::new(AtomicBool::new(true))
+        }
+    }
+
+    // Make a handle for a TC which has been sent with `frame_counter` and is
+    // waiting to be acknowledged by the rover
+    fn sent_tc(backend: &mut TmTcIfBackend, frame_counter: u32) -> TcHandle {
+        let handle = TcHandle::new(String::from("TcTest"));
+        handle.set_sent(frame_counter, Utc::now());
+        backend.tcs_awaiting_ack.push_back(handle.clone());
+        handle
+    }
+
+    fn ack(
+        backend: &mut TmTcIfBackend, frame_counter: u32, status: TcAckStatus) {
+
+        let tm = AutoTmTc::from_data(&TmTcAck { frame_counter, status })
+            .unwrap();
+        backend.tc_ack_received(&tm);
+    }
+
+    // Connect a test backend to a socket standing in for the rover, which is
+    // returned so the connection stays open
+    fn connect_backend(backend: &mut TmTcIfBackend) -> TcpStream {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap())
+            .unwrap();
+        backend.stream = Some(stream);
+        listener.accept().unwrap().0
+    }
+
+    fn pending_tc(priority: bool) -> (PendingTc, TcHandle) {
+        let handle = TcHandle::new(String::from("TcTest"));
+        let tc = PendingTc {
+            packet: AutoTmTc::from_data(&TcHeartbeat::new()).unwrap(),
+            handle: Some(handle.clone()),
+            priority,
+            exec_after: None
+        };
+
+        (tc, handle)
+    }
+
+    #[test]
+    fn test_tc_ack_progress() {
+        let mut backend = test_backend(TmTcIfConfig::default());
+        let handle = sent_tc(&mut backend, 3);
+
+        assert_eq!(handle.status(), TcStatus::Sent);
+        assert_eq!(handle.frame_counter(), Some(3));
+
+        ack(&mut backend, 3, TcAckStatus::Accepted);
+        assert_eq!(handle.status(), TcStatus::Accepted);
+        assert_eq!(backend.tcs_awaiting_ack.len(), 1);
+
+        // Once the TC is finished with it's no longer tracked
+        ack(&mut backend, 3, TcAckStatus::Completed);
+        assert_eq!(handle.status(), TcStatus::Completed);
+        assert!(backend.tcs_awaiting_ack.is_empty());
+    }
+
+    #[test]
+    fn test_tc_ack_reasons() {
+        let mut backend = test_backend(TmTcIfConfig::default());
+        let rejected = sent_tc(&mut backend, 0);
+        let failed = sent_tc(&mut backend, 1);
+
+        ack(&mut backend, 0, TcAckStatus::Rejected { 
+            reason: String::from("Wrong mode") });
+        ack(&mut backend, 1, TcAckStatus::Accepted);
+        ack(&mut backend, 1, TcAckStatus::Failed { 
+            reason: String::from("Stalled") });
+
+        assert_eq!(
+            rejected.status(), TcStatus::Rejected(String::from("Wrong mode")));
+        assert_eq!(failed.status(), TcStatus::Failed(String::from("Stalled")));
+        assert!(backend.tcs_awaiting_ack.is_empty());
+    }
+
+    #[test]
+    fn test_tc_acks_out_of_order() {
+        let mut backend = test_backend(TmTcIfConfig::default());
+        let first = sent_tc(&mut backend, 7);
+        let second = sent_tc(&mut backend, 8);
+
+        // The later TC's acks arrive first, and the completion of each TC
+        // overtakes its acceptance
+        ack(&mut backend, 8, TcAckStatus::Completed);
+        ack(&mut backend, 7, TcAckStatus::Completed);
+        ack(&mut backend, 8, TcAckStatus::Accepted);
+        ack(&mut backend, 7, TcAckStatus::Accepted);
+
+        assert_eq!(first.status(), TcStatus::Completed);
+        assert_eq!(second.status(), TcStatus::Completed);
+        assert!(backend.tcs_awaiting_ack.is_empty());
+    }
+
+    #[test]
+    fn test_tc_ack_unknown_frame_counter() {
+        let mut backend = test_backend(TmTcIfConfig::default());
+        let handle = sent_tc(&mut backend, 2);
+
+        // Acks for TCs which aren't tracked, like heartbeats, change nothing
+        ack(&mut backend, 99, TcAckStatus::Completed);
+
+        assert_eq!(handle.status(), TcStatus::Sent);
+        assert_eq!(backend.tcs_awaiting_ack.len(), 1);
+    }
+
+    #[test]
+    fn test_tc_wait_timeout() {
+        let mut backend = test_backend(TmTcIfConfig::default());
+        let handle = sent_tc(&mut backend, 0);
+
+        match handle.wait_for_ack(Duration::from_millis(20)) {
+            Err(TmTcError::Timeout(_)) => (),
+            r => panic!("Expected a timeout but got {:?}", r)
         }
+
+        // Accepted is enough for `wait_for_ack` but not for `wait`
+        ack(&mut backend, 0, TcAckStatus::Accepted);
+        assert_eq!(
+            handle.wait_for_ack(Duration::from_millis(20)).unwrap(),
+            TcStatus::Accepted);
+        assert!(handle.wait(Duration::from_millis(20)).is_err());
+    }
+
+    #[test]
+    fn test_tc_wait_woken_by_ack() {
+        let mut backend = test_backend(TmTcIfConfig::default());
+        let handle = sent_tc(&mut backend, 0);
+
+        let waiter = {
+            let handle = handle.clone();
+            thread::spawn(move || handle.wait(Duration::from_secs(5)))
+        };
+
+        thread::sleep(Duration::from_millis(20));
+        ack(&mut backend, 0, TcAckStatus::Completed);
+
+        assert_eq!(waiter.join().unwrap().unwrap(), TcStatus::Completed);
+    }
+
+    #[test]
+    fn test_buffer_full_drops_oldest() {
+        let mut backend = test_backend(TmTcIfConfig {
+            max_buffered_tcs: 2,
+            ..TmTcIfConfig::default()
+        });
+
+        let handles: Vec<TcHandle> = (0..3)
+            .map(|_| {
+                let (tc, handle) = pending_tc(false);
+                backend.buffer_tc(tc);
+                handle
+            })
+            .collect();
+
+        assert_eq!(backend.tc_queue.len(), 2);
+        assert_eq!(
+            handles[0].status(),
+            TcStatus::Undelivered(String::from("TC buffer full")));
+        assert_eq!(handles[1].status(), TcStatus::Queued);
+        assert_eq!(handles[2].status(), TcStatus::Queued);
+    }
+
+    #[test]
+    fn test_priority_tc_discards_queue() {
+        let mut backend = test_backend(TmTcIfConfig::default());
+
+        let (normal, normal_handle) = pending_tc(false);
+        let (first, first_handle) = pending_tc(true);
+        let (second, second_handle) = pending_tc(true);
+
+        backend.buffer_tc(normal);
+        backend.buffer_tc(first);
+        backend.buffer_tc(second);
+
+        // Priority TCs don't discard each other
+        assert_eq!(backend.tc_queue.len(), 2);
+        assert!(matches!(normal_handle.status(), TcStatus::Undelivered(_)));
+        assert_eq!(first_handle.status(), TcStatus::Queued);
+        assert_eq!(second_handle.status(), TcStatus::Queued);
+        assert_eq!(backend.link_stats.lock().unwrap().tcs_dropped, 1);
+    }
+
+    #[test]
+    fn test_resent_priority_tc_acked_under_old_frame_counter() {
+        let mut backend = test_backend(TmTcIfConfig {
+            priority_tc_retry_period: Duration::from_secs(0),
+            ..TmTcIfConfig::default()
+        });
+        let _rover = connect_backend(&mut backend);
+        let mut sent = vec![];
+
+        let (abort, handle) = pending_tc(true);
+        backend.buffer_tc(abort);
+        backend.send_pending_tcs(&mut sent).unwrap();
+        assert_eq!(handle.frame_counter(), Some(0));
+
+        // The rover is slow to answer so the TC is resent
+        backend.retry_priority_tcs();
+        backend.send_pending_tcs(&mut sent).unwrap();
+        assert_eq!(handle.frame_counter(), Some(1));
+        assert_eq!(sent.len(), 2);
+
+        // The ack for the first transmission still counts
+        ack(&mut backend, 0, TcAckStatus::Completed);
+        assert_eq!(handle.status(), TcStatus::Completed);
+        assert!(backend.tcs_awaiting_ack.is_empty());
+
+        // and it isn't resent again
+        backend.retry_priority_tcs();
+        assert!(backend.tc_queue.is_empty());
+        assert!(backend.priority_tcs_unacked.is_empty());
+    }
+
+    #[test]
+    fn test_resend_keeps_acknowledged_status() {
+        let mut backend = test_backend(TmTcIfConfig {
+            priority_tc_retry_period: Duration::from_secs(0),
+            ..TmTcIfConfig::default()
+        });
+        let _rover = connect_backend(&mut backend);
+        let mut sent = vec![];
+
+        let (abort, handle) = pending_tc(true);
+        backend.buffer_tc(abort);
+        backend.send_pending_tcs(&mut sent).unwrap();
+
+        // A resend is queued just before the acks arrive
+        backend.retry_priority_tcs();
+        ack(&mut backend, 0, TcAckStatus::Accepted);
+        backend.send_pending_tcs(&mut sent).unwrap();
+        assert_eq!(handle.status(), TcStatus::Accepted);
+
+        ack(&mut backend, 0, TcAckStatus::Completed);
+        handle.set_sent(5, Utc::now());
+        assert_eq!(handle.status(), TcStatus::Completed);
     }
-}
\ No newline at end of file
+}
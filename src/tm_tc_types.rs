@@ -0,0 +1,215 @@
+// ---------------------------------------------------------------------------
+// TELEMETRY AND TELECOMMAND TYPES
+//
+// Types used both by the TmTcInterface module, to describe and check TM/TC
+// data in general, and by the TCs and TMs themselves. They're kept here so
+// the interface doesn't depend on the TC constructor.
+// ---------------------------------------------------------------------------
+
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use crate::tm_tc_error::TmTcError;
+
+// ---------------------------------------------------------------------------
+// ROVER MODE
+// ---------------------------------------------------------------------------
+
+// The rover's operating modes, which restrict the TCs it will execute
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub enum RoverMode {
+    // Entered on faults, the rover won't move until commanded out of Safe
+    Safe,
+
+    // Idle and ready to be put into Manual or Autonomous
+    Standby,
+
+    // Driven directly by locomotion TCs
+    Manual,
+
+    // Following waypoints using the rover's own navigation
+    Autonomous
+}
+
+impl RoverMode {
+
+    // Returns true if the rover may be commanded from this mode to `to`.
+    // Safe and Standby can always be entered, but Manual and Autonomous can
+    // only be entered from Standby, so the rover is always brought to a stop
+    // between driving modes and must be recovered from Safe via Standby.
+    // Asking for the mode the rover is already in is always allowed, so a
+    // repeated TC isn't refused.
+    pub fn can_change_to(&self, to: RoverMode) -> bool {
+        if *self == to {
+            return true
+        }
+
+        match to {
+            RoverMode::Safe | RoverMode::Standby => true,
+            RoverMode::Manual | RoverMode::Autonomous => {
+                *self == RoverMode::Standby
+            }
+        }
+    }
+}
+
+impl fmt::Display for RoverMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TC PARAMETER SCHEMA
+// ---------------------------------------------------------------------------
+
+// Names of the rover's modes, as they're written in TCs
+pub const ROVER_MODE_NAMES: &[&str] = &["Safe", "Standby", "Manual", "Autonomous"];
+
+// The kind of value a TC parameter takes
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParamKind {
+    // A number between `min` and `max` inclusive
+    Number { min: f64, max: f64 },
+
+    // A whole number of 0 or more, like a frame counter
+    Count,
+
+    // One of a fixed set of names
+    Choice(&'static [&'static str]),
+
+    // Anything more complicated, like a list, entered as JSON
+    Json
+}
+
+// Describes a single TC parameter, so the GUI can build an input field for it
+// without knowing anything about the TC
+#[derive(Clone, Copy, Debug)]
+pub struct ParamSchema {
+    // Name of the parameter's field in the TC
+    pub name: &'static str,
+
+    // Units of the parameter, or an empty string if it doesn't have any
+    pub units: &'static str,
+
+    pub kind: ParamKind,
+
+    // Value to start the parameter's input field with
+    pub default: &'static str
+}
+
+impl ParamSchema {
+
+    // Parse the text entered for this parameter into the JSON value to put
+    // in the TC, checking it's the right kind of value and within range.
+    pub fn parse(&self, text: &str) -> Result<serde_json::Value, TmTcError> {
+        let text = text.trim();
+
+        match self.kind {
+            ParamKind::Number { min, max } => match text.parse::<f64>() {
+                Ok(v) => {
+                    check_param(self.name, v, min, max)?;
+                    Ok(serde_json::Value::from(v))
+                },
+                Err(_) => Err(TmTcError::InvalidParameter(format!(
+                    "{} must be a number but is \"{}\"", self.name, text)))
+            },
+            ParamKind::Count => match text.parse::<u32>() {
+                Ok(v) => Ok(serde_json::Value::from(v)),
+                Err(_) => Err(TmTcError::InvalidParameter(format!(
+                    "{} must be a whole number but is \"{}\"", 
+                    self.name, text)))
+            },
+            ParamKind::Choice(options) => {
+                if options.contains(&text) {
+                    Ok(serde_json::Value::from(text))
+                }
+                else {
+                    Err(TmTcError::InvalidParameter(format!(
+                        "{} must be one of {} but is \"{}\"", 
+                        self.name, options.join(", "), text)))
+                }
+            },
+            ParamKind::Json => serde_json::from_str(text).map_err(|e| {
+                TmTcError::InvalidParameter(format!(
+                    "{} isn't valid JSON: {}", self.name, e))
+            })
+        }
+    }
+
+    // Format a value of this parameter as text for its input field, the
+    // opposite of `parse`
+    pub fn format_value(&self, value: &serde_json::Value) -> String {
+        match (self.kind, value) {
+            (ParamKind::Json, v) => v.to_string(),
+            (_, serde_json::Value::String(s)) => s.clone(),
+            (_, v) => v.to_string()
+        }
+    }
+}
+
+// Shows the parameter as a label for its input field, for example
+// "distance_m (m) [-10.00 to 10.00]"
+impl fmt::Display for ParamSchema {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        if !self.units.is_empty() {
+            write!(f, " ({})", self.units)?;
+        }
+
+        match self.kind {
+            ParamKind::Number { min, max } => write!(
+                f, " [{:.2} to {:.2}]", min, max),
+            ParamKind::Count => Ok(()),
+            ParamKind::Choice(options) => write!(
+                f, " [{}]", options.join(", ")),
+            ParamKind::Json => write!(f, " [JSON]")
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// PUBLIC FUNCTIONS
+// ---------------------------------------------------------------------------
+
+// Check that a TC parameter is a finite number between `min` and `max`
+// inclusive.
+pub fn check_param(name: &str, value: f64, min: f64, max: f64) 
+    -> Result<(), TmTcError> {
+
+    if value.is_finite() && value >= min && value <= max {
+        Ok(())
+    }
+    else {
+        Err(TmTcError::InvalidParameter(format!(
+            "{} must be between {} and {} but is {}", name, min, max, value)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mode_transitions() {
+        use RoverMode::*;
+
+        let modes = [Safe, Standby, Manual, Autonomous];
+
+        // Whether each mode (row) can change to each mode (column)
+        let allowed = [
+            [true, true, false, false],
+            [true, true, true, true],
+            [true, true, true, false],
+            [true, true, false, true]
+        ];
+
+        for (from, row) in modes.iter().zip(allowed.iter()) {
+            for (to, expected) in modes.iter().zip(row.iter()) {
+                assert_eq!(
+                    from.can_change_to(*to), *expected,
+                    "{} to {}", from, to);
+            }
+        }
+    }
+}
@@ -0,0 +1,357 @@
+use vek::*;
+use std::f32::consts::PI;
+
+use crate::virtspace::pipeline::RoverPose;
+use crate::virtspace::terrain::{Dem, ground_height};
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+// Field of view and clipping planes of the perspective projection
+const FOV_RAD: f32 = 1.3;
+const NEAR_PLANE_M: f32 = 0.05;
+const FAR_PLANE_M: f32 = 1000.0;
+
+// How far the camera moves for each pixel the mouse is dragged. Panning is
+// scaled by the distance to the target so it feels the same at any zoom.
+const ORBIT_RAD_PER_PX: f32 = 0.01;
+const PAN_PER_PX: f32 = 0.002;
+
+// Each step of the scroll wheel moves the camera this much closer
+const ZOOM_FACTOR_PER_STEP: f32 = 0.9;
+
+// Limits on the camera's distance from its target and its elevation, so it
+// can't pass through the target or flip over the top of it
+const MIN_DISTANCE_M: f32 = 1.0;
+const MAX_DISTANCE_M: f32 = 200.0;
+const MIN_PITCH_RAD: f32 = 0.05;
+const MAX_PITCH_RAD: f32 = 1.55;
+
+// Preset views
+const TOP_DOWN_PITCH_RAD: f32 = MAX_PITCH_RAD;
+const CHASE_PITCH_RAD: f32 = 0.4;
+const CHASE_DISTANCE_M: f32 = 6.0;
+
+// Finding the point on the ground under the mouse steps along the ray from
+// the camera this many times to find where it first goes underground, then
+// narrows that down this many times
+const GROUND_SEARCH_STEPS: usize = 64;
+const GROUND_REFINE_STEPS: usize = 16;
+
+// ---------------------------------------------------------------------------
+// CAMERA
+// ---------------------------------------------------------------------------
+
+// How the camera is controlled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+    // Orbit, pan and zoom anywhere
+    Free,
+
+    // Looking straight down, panning and zooming over the ground
+    TopDown,
+
+    // Following behind the rover, orbiting and zooming around it
+    Chase
+}
+
+// An orbiting camera, looking at a target point from a distance, heading
+// (yaw) and elevation (pitch). The target is on the ground, except in chase
+// mode where it's the rover, and yaw is then relative to behind the rover.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Camera {
+    mode: CameraMode,
+    target: Vec3<f32>,
+    distance_m: f32,
+    yaw_rad: f32,
+    pitch_rad: f32
+}
+
+impl Default for Camera {
+    // A free camera looking north over the world grid
+    fn default() -> Self {
+        Camera {
+            mode: CameraMode::Free,
+            target: Vec3::new(14.0, 7.0, 0.0),
+            distance_m: 14.0,
+            yaw_rad: -PI / 2.0,
+            pitch_rad: 0.78
+        }
+    }
+}
+
+impl Camera {
+
+    // Switch to one of the preset views. `rover` is the rover's current pose,
+    // if it's known, which the top-down and chase views centre on.
+    pub fn set_mode(&mut self, mode: CameraMode, rover: Option<RoverPose>) {
+        // Leaving chase mode, keep looking at the rover from the same place
+        if self.mode == CameraMode::Chase {
+            if let Some(r) = rover {
+                self.target = r.position;
+                self.yaw_rad += r.heading_rad() + PI;
+            }
+        }
+
+        match mode {
+            CameraMode::Free => (),
+            CameraMode::TopDown => {
+                self.pitch_rad = TOP_DOWN_PITCH_RAD;
+                self.yaw_rad = -PI / 2.0;
+                if let Some(r) = rover {
+                    self.target = Vec3::new(r.position.x, r.position.y, 0.0);
+                }
+            },
+            CameraMode::Chase => {
+                self.pitch_rad = CHASE_PITCH_RAD;
+                self.yaw_rad = 0.0;
+                self.distance_m = CHASE_DISTANCE_M;
+            }
+        }
+
+        self.mode = mode;
+    }
+
+    // Orbit around the target by a mouse drag in pixels. The top-down view
+    // can only be turned, not tilted.
+    pub fn orbit(&mut self, dx_px: f32, dy_px: f32) {
+        self.yaw_rad -= dx_px * ORBIT_RAD_PER_PX;
+
+        if self.mode != CameraMode::TopDown {
+            self.pitch_rad = (self.pitch_rad + dy_px * ORBIT_RAD_PER_PX)
+                .clamp(MIN_PITCH_RAD, MAX_PITCH_RAD);
+        }
+    }
+
+    // Move the target over the ground by a mouse drag in pixels. Panning
+    // while chasing the rover switches to the free camera.
+    pub fn pan(&mut self, dx_px: f32, dy_px: f32, rover: Option<RoverPose>) {
+        if dx_px == 0.0 && dy_px == 0.0 {
+            return
+        }
+
+        if self.mode == CameraMode::Chase {
+            self.set_mode(CameraMode::Free, rover);
+        }
+
+        // Directions on the ground to the camera's right and away from it
+        let right = Vec3::new(-self.yaw_rad.sin(), self.yaw_rad.cos(), 0.0);
+        let forward = Vec3::new(-self.yaw_rad.cos(), -self.yaw_rad.sin(), 0.0);
+
+        let scale = PAN_PER_PX * self.distance_m;
+        self.target += (right * -dx_px + forward * dy_px) * scale;
+    }
+
+    // Zoom in by a number of scroll wheel steps, negative to zoom out
+    pub fn zoom(&mut self, steps: f32) {
+        self.distance_m = (self.distance_m * ZOOM_FACTOR_PER_STEP.powf(steps))
+            .clamp(MIN_DISTANCE_M, MAX_DISTANCE_M);
+    }
+
+    // Get the model-view-projection matrix for a view of the given size in
+    // pixels, following the rover in chase mode
+    pub fn mvp(&self, width: f32, height: f32, rover: Option<RoverPose>)
+        -> Mat4<f32> {
+
+        let (target, yaw_rad) = match (self.mode, rover) {
+            (CameraMode::Chase, Some(r)) => {
+                (r.position, self.yaw_rad + r.heading_rad() + PI)
+            },
+            _ => (self.target, self.yaw_rad)
+        };
+
+        let eye = target + Vec3::new(
+            self.pitch_rad.cos() * yaw_rad.cos(),
+            self.pitch_rad.cos() * yaw_rad.sin(),
+            self.pitch_rad.sin()) * self.distance_m;
+
+        Mat4::perspective_fov_rh_no(
+                FOV_RAD, width, height, NEAR_PLANE_M, FAR_PLANE_M)
+            * Mat4::look_at_rh(eye, target, Vec3::unit_z())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// SCREEN COORDINATES
+// ---------------------------------------------------------------------------
+
+// Find the point on the ground under a point on the view, given in pixels
+// from its top left corner. The ground is the DEM if there is one and flat at
+// z = 0 elsewhere. `mvp` is the matrix the view was drawn with and `size` is
+// the size of the view in pixels. Returns None if the point is above the
+// horizon.
+pub fn screen_to_ground(
+    mvp: &Mat4<f32>, size: Vec2<f32>, screen: Vec2<f32>, dem: Option<&Dem>)
+    -> Option<Vec3<f32>> {
+
+    // Nothing can be found until the view has been drawn
+    if size.x <= 0.0 || size.y <= 0.0 {
+        return None
+    }
+
+    let ndc_x = 2.0 * screen.x / size.x - 1.0;
+    let ndc_y = 1.0 - 2.0 * screen.y / size.y;
+
+    // Cast a ray from the near plane to the far plane through the point
+    let inv_mvp = mvp.inverted();
+    let near = inv_mvp * Vec4::new(ndc_x, ndc_y, -1.0, 1.0);
+    let far = inv_mvp * Vec4::new(ndc_x, ndc_y, 1.0, 1.0);
+    let near = Vec3::from(near) / near.w;
+    let far = Vec3::from(far) / far.w;
+
+    let dz = far.z - near.z;
+
+    // Only look between where the ray passes the highest and lowest ground
+    let (lowest, highest) = match dem {
+        Some(d) => {
+            let (l, h) = d.height_range();
+            (l.min(0.0), h.max(0.0))
+        },
+        None => (0.0, 0.0)
+    };
+    let t_top = ((highest - near.z) / dz).max(0.0);
+    let t_lowest = (lowest - near.z) / dz;
+    let t_bottom = t_lowest.min(1.0);
+
+    if dz >= 0.0 || t_top > t_bottom {
+        return None
+    }
+
+    let ray_at = |t: f32| Vec3::lerp(near, far, t);
+
+    // Without a DEM the ground is flat, so the ray first reaches it at once
+    if dem.is_none() {
+        return Some(ray_at(t_top))
+    }
+
+    let underground = |t: f32| {
+        let p = ray_at(t);
+        p.z <= ground_height(dem, p.xy())
+    };
+
+    let mut above_t = t_top;
+
+    for i in 1..=GROUND_SEARCH_STEPS {
+        let t = t_top 
+            + (t_bottom - t_top) * i as f32 / GROUND_SEARCH_STEPS as f32;
+
+        // By the last step the ray is down to the lowest ground, even if
+        // rounding leaves it a hair above
+        let lowest_reached = i == GROUND_SEARCH_STEPS && t_lowest <= 1.0;
+
+        if lowest_reached || underground(t) {
+            let mut below_t = t;
+
+            for _ in 0..GROUND_REFINE_STEPS {
+                let mid_t = 0.5 * (above_t + below_t);
+                if underground(mid_t) {
+                    below_t = mid_t;
+                }
+                else {
+                    above_t = mid_t;
+                }
+            }
+
+            return Some(ray_at(below_t))
+        }
+
+        above_t = t;
+    }
+
+    // The ground is further away than the far plane
+    None
+}
+
+// Find where a point in the virtual space is drawn on the view, in pixels
+// from its top left corner. Returns None if the point is behind the camera.
+pub fn world_to_screen(mvp: &Mat4<f32>, size: Vec2<f32>, point: Vec3<f32>)
+    -> Option<Vec2<f32>> {
+
+    let clip = *mvp * Vec4::from_point(point);
+
+    if clip.w <= 0.0 {
+        return None
+    }
+
+    Some(Vec2::new(
+        (clip.x / clip.w + 1.0) * 0.5 * size.x,
+        (1.0 - clip.y / clip.w) * 0.5 * size.y))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const VIEW_SIZE: Vec2<f32> = Vec2 { x: 800.0, y: 600.0 };
+
+    // A DEM under the default view, rising 0.2 m for every metre east
+    fn slope_dem() -> Dem {
+        let row = (0..20)
+            .map(|i| format!("{:.1}", 0.2 * i as f32))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let heights = vec![format!("[{}]", row); 10].join(", ");
+
+        Dem::from_json(&format!(
+            r#"{{ "origin_m": [5.0, 2.0], "resolution_m": 1.0,
+                "heights_m": [{}] }}"#, heights)).unwrap()
+    }
+
+    // Check a point on the ground is found again under where it's drawn
+    fn assert_round_trip(
+        mvp: &Mat4<f32>, point: Vec3<f32>, dem: Option<&Dem>) {
+
+        let screen = world_to_screen(mvp, VIEW_SIZE, point).unwrap();
+        let ground = screen_to_ground(mvp, VIEW_SIZE, screen, dem).unwrap();
+
+        assert!(
+            ground.distance(point) < 1e-2,
+            "{:?} was found at {:?}", point, ground);
+    }
+
+    #[test]
+    fn test_screen_to_flat_ground() {
+        let mvp = Camera::default().mvp(VIEW_SIZE.x, VIEW_SIZE.y, None);
+
+        for (x, y) in [(14.0, 7.0), (12.0, 5.0), (20.0, 3.0)].iter() {
+            assert_round_trip(&mvp, Vec3::new(*x, *y, 0.0), None);
+        }
+    }
+
+    #[test]
+    fn test_screen_to_dem() {
+        let mvp = Camera::default().mvp(VIEW_SIZE.x, VIEW_SIZE.y, None);
+        let dem = slope_dem();
+
+        for (x, y) in [(14.0, 7.0), (12.0, 5.0), (20.5, 3.5)].iter() {
+            let point = Vec2::new(*x, *y);
+            let height = dem.height_at(point).unwrap();
+            assert_round_trip(
+                &mvp, Vec3::new(point.x, point.y, height), Some(&dem));
+        }
+
+        // Off the DEM the ground is flat
+        assert_round_trip(&mvp, Vec3::new(2.0, 7.0, 0.0), Some(&dem));
+    }
+
+    #[test]
+    fn test_screen_above_horizon() {
+        let mut camera = Camera::default();
+        camera.orbit(0.0, -100.0);
+        let mvp = camera.mvp(VIEW_SIZE.x, VIEW_SIZE.y, None);
+        let top = Vec2::new(400.0, 0.0);
+
+        assert_eq!(screen_to_ground(&mvp, VIEW_SIZE, top, None), None);
+        assert_eq!(
+            screen_to_ground(&mvp, VIEW_SIZE, top, Some(&slope_dem())), None);
+
+        // The bottom of the view still looks down at the ground
+        let bottom = Vec2::new(400.0, 600.0);
+        assert!(screen_to_ground(&mvp, VIEW_SIZE, bottom, None).is_some());
+
+        // Nor is there any ground before the view has been drawn
+        assert_eq!(
+            screen_to_ground(&mvp, Vec2::zero(), Vec2::zero(), None), None);
+    }
+}
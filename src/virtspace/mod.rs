@@ -1,6 +1,9 @@
 pub mod objects;
 pub mod shaders;
 pub mod pipeline;
+pub mod camera;
+pub mod path_plan;
+pub mod terrain;
 
 pub use pipeline::VirtSpacePipeline;
 
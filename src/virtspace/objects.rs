@@ -1,4 +1,30 @@
 use vek::*;
+use std::time::Instant;
+
+use crate::virtspace::pipeline::{PoseTrail, TrailColour};
+use crate::virtspace::terrain::{Dem, ground_height};
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+// Speed shown in red on a trail coloured by speed
+const TRAIL_FULL_SPEED_MPS: f32 = 0.5;
+
+// Height of the trail above the ground, so it's drawn over the world grid
+// and terrain
+const TRAIL_HEIGHT_M: f32 = 0.02;
+
+// Height of the planned path above the ground, over the trail, and the size
+// of the cross marking each waypoint
+const PATH_HEIGHT_M: f32 = 0.03;
+const WAYPOINT_MARKER_SIZE_M: f32 = 0.2;
+
+// Colour of the terrain, and how it's lit. Slopes facing the sun are drawn in
+// the full colour, and those facing away from it in the ambient fraction.
+const TERRAIN_COLOUR: [f32; 3] = [0.55, 0.45, 0.35];
+const TERRAIN_SUN_DIRECTION: [f32; 3] = [0.4, 0.3, 0.85];
+const TERRAIN_AMBIENT: f32 = 0.3;
 
 // ---------------------------------------------------------------------------
 // WORLD GRID
@@ -142,3 +168,190 @@ impl RoverBody {
         ]
     }
 }
+
+// ---------------------------------------------------------------------------
+// COLOURED VERTICES
+// ---------------------------------------------------------------------------
+
+// Points drawn with a colour each, blended along the lines or across the
+// triangles between them. The trail, planned path and terrain are all built
+// as these.
+pub struct ColouredVertices<'a> {
+    pub mvp: &'a Mat4<f32>,
+    pub positions: &'a [Vec4<f32>],
+    pub colours: &'a [Rgba<f32>]
+}
+
+// ---------------------------------------------------------------------------
+// ROVER TRAIL
+// ---------------------------------------------------------------------------
+
+pub struct RoverTrail;
+
+impl RoverTrail {
+
+    // Build the trail's points on the ground, which is the DEM if there is 
+    // one, with their colours, and the order to draw them in as lines. Points
+    // fade to black as they decay.
+    pub fn build(trail: &PoseTrail, now: Instant, dem: Option<&Dem>) 
+        -> (Vec<Vec4<f32>>, Vec<Rgba<f32>>, Vec<u32>) {
+
+        let points = trail.points();
+        let mut pos = Vec::with_capacity(points.len());
+        let mut colours = Vec::with_capacity(points.len());
+        let mut draw_order: Vec<u32> = vec![];
+
+        for (i, p) in points.iter().enumerate() {
+            let z = ground_height(dem, p.position.xy()) + TRAIL_HEIGHT_M;
+            pos.push(Vec4::new(p.position.x, p.position.y, z, 1.0));
+
+            let colour = match trail.config.colour {
+                TrailColour::Speed => {
+                    let f = (p.speed_mps / TRAIL_FULL_SPEED_MPS).min(1.0);
+                    if f < 0.5 {
+                        Rgba::lerp(Rgba::blue(), Rgba::green(), f * 2.0)
+                    }
+                    else {
+                        Rgba::lerp(Rgba::green(), Rgba::red(), f * 2.0 - 1.0)
+                    }
+                },
+                TrailColour::Time => {
+                    let f = i as f32 / (points.len().max(2) - 1) as f32;
+                    Rgba::lerp(Rgba::blue(), Rgba::yellow(), f)
+                }
+            };
+
+            let brightness = match trail.config.decay_s {
+                Some(d) => {
+                    let age_s = now.saturating_duration_since(p.received)
+                        .as_secs_f32();
+                    (1.0 - age_s / d).max(0.0)
+                },
+                None => 1.0
+            };
+
+            colours.push(Rgba::new(
+                colour.r * brightness, 
+                colour.g * brightness, 
+                colour.b * brightness, 
+                1.0));
+
+            if i > 0 {
+                draw_order.push((i - 1) as u32);
+                draw_order.push(i as u32);
+            }
+        }
+
+        (pos, colours, draw_order)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// PLANNED PATH
+// ---------------------------------------------------------------------------
+
+pub struct PlannedPath;
+
+impl PlannedPath {
+
+    // Build the path's lines, joining the rover to the first waypoint and 
+    // then each waypoint to the next, with a cross marking each waypoint.
+    // Waypoints are on the ground, which is the DEM if there is one. Returns
+    // the points with their colours and the order to draw them in as lines.
+    pub fn build(
+        waypoints: &[Vec2<f32>], rover: Option<Vec3<f32>>, dem: Option<&Dem>) 
+        -> (Vec<Vec4<f32>>, Vec<Rgba<f32>>, Vec<u32>) {
+
+        let mut pos: Vec<Vec4<f32>> = vec![];
+        let mut colours: Vec<Rgba<f32>> = vec![];
+        let mut draw_order: Vec<u32> = vec![];
+
+        let path_colour = Rgba::cyan();
+        let marker_colour = Rgba::white();
+        let on_ground = |p: Vec2<f32>| Vec4::new(
+            p.x, p.y, ground_height(dem, p) + PATH_HEIGHT_M, 1.0);
+
+        if waypoints.is_empty() {
+            return (pos, colours, draw_order)
+        }
+
+        // The leg from the rover is dimmer, as the rover will have moved by 
+        // the time the path is sent
+        if let Some(r) = rover {
+            pos.push(on_ground(r.xy()));
+            colours.push(path_colour * 0.4);
+            pos.push(on_ground(waypoints[0]));
+            colours.push(path_colour * 0.4);
+            draw_order.push(0);
+            draw_order.push(1);
+        }
+
+        let first_waypoint = pos.len();
+
+        for (i, w) in waypoints.iter().enumerate() {
+            pos.push(on_ground(*w));
+            colours.push(path_colour);
+
+            if i > 0 {
+                draw_order.push((first_waypoint + i - 1) as u32);
+                draw_order.push((first_waypoint + i) as u32);
+            }
+        }
+
+        let s = WAYPOINT_MARKER_SIZE_M * 0.5;
+
+        for w in waypoints {
+            for (dx, dy) in [(-s, -s), (s, s), (-s, s), (s, -s)].iter() {
+                draw_order.push(pos.len() as u32);
+                pos.push(on_ground(*w + Vec2::new(*dx, *dy)));
+                colours.push(marker_colour);
+            }
+        }
+
+        (pos, colours, draw_order)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// TERRAIN MESH
+// ---------------------------------------------------------------------------
+
+pub struct TerrainMesh;
+
+impl TerrainMesh {
+
+    // Build the DEM's points with their colours, shaded by how much they face
+    // the sun, and the order to draw them in as triangles, two for each cell
+    // of the grid.
+    pub fn build(dem: &Dem) -> (Vec<Vec4<f32>>, Vec<Rgba<f32>>, Vec<u32>) {
+        let (num_x, num_y) = (dem.num_x(), dem.num_y());
+
+        let mut pos = Vec::with_capacity(num_x * num_y);
+        let mut colours = Vec::with_capacity(num_x * num_y);
+        let mut draw_order: Vec<u32> = vec![];
+
+        let sun = Vec3::from(TERRAIN_SUN_DIRECTION).normalized();
+        let base = Vec3::from(TERRAIN_COLOUR);
+
+        for j in 0..num_y {
+            for i in 0..num_x {
+                pos.push(Vec4::from_point(dem.point(i, j)));
+
+                let lit = dem.normal(i, j).dot(sun).max(0.0);
+                let shade = TERRAIN_AMBIENT + (1.0 - TERRAIN_AMBIENT) * lit;
+                let c = base * shade;
+                colours.push(Rgba::new(c.x, c.y, c.z, 1.0));
+
+                if i < num_x - 1 && j < num_y - 1 {
+                    let corner = (j * num_x + i) as u32;
+                    let (right, up) = (corner + 1, corner + num_x as u32);
+
+                    draw_order.extend(&[corner, right, up + 1]);
+                    draw_order.extend(&[corner, up + 1, up]);
+                }
+            }
+        }
+
+        (pos, colours, draw_order)
+    }
+}
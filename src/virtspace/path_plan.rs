@@ -0,0 +1,183 @@
+use vek::*;
+
+use crate::virtspace::camera::{screen_to_ground, world_to_screen};
+use crate::virtspace::terrain::{Dem, ground_height};
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+// How close to a waypoint on the view a click must be to pick it, in pixels
+const WAYPOINT_PICK_RADIUS_PX: f32 = 10.0;
+
+// ---------------------------------------------------------------------------
+// PATH PLAN
+// ---------------------------------------------------------------------------
+
+// A path planned by the operator on the view, as a list of waypoints on the
+// ground. Waypoints are added by clicking the ground, moved by dragging them
+// and deleted by right clicking them. Screen points are in pixels from the
+// top left corner of the view, `mvp` is the matrix the view was drawn with,
+// `size` is the size of the view in pixels and `dem` is the terrain, if any,
+// which the waypoints sit on.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PathPlan {
+    waypoints: Vec<Vec2<f32>>,
+
+    // Index of the waypoint being dragged
+    dragging: Option<usize>
+}
+
+impl PathPlan {
+
+    // Get the waypoints in the order they're to be visited
+    pub fn waypoints(&self) -> &[Vec2<f32>] {
+        &self.waypoints
+    }
+
+    // Find the waypoint drawn nearest to a screen point, if any is close
+    // enough to pick
+    fn pick(
+        &self, mvp: &Mat4<f32>, size: Vec2<f32>, screen: Vec2<f32>, 
+        dem: Option<&Dem>) -> Option<usize> {
+
+        self.waypoints.iter()
+            .enumerate()
+            .filter_map(|(i, w)| {
+                let point = Vec3::new(w.x, w.y, ground_height(dem, *w));
+                world_to_screen(mvp, size, point)
+                    .map(|s| (i, s.distance(screen)))
+            })
+            .filter(|(_, d)| *d <= WAYPOINT_PICK_RADIUS_PX)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+    }
+
+    // Start dragging the waypoint under a screen point, or add a new one to
+    // the end of the path there if there's none
+    pub fn press(
+        &mut self, mvp: &Mat4<f32>, size: Vec2<f32>, screen: Vec2<f32>,
+        dem: Option<&Dem>) {
+
+        if let Some(i) = self.pick(mvp, size, screen, dem) {
+            self.dragging = Some(i);
+            return
+        }
+
+        if let Some(g) = screen_to_ground(mvp, size, screen, dem) {
+            self.waypoints.push(g.xy());
+            self.dragging = Some(self.waypoints.len() - 1);
+        }
+    }
+
+    // Move the waypoint being dragged to the ground under a screen point
+    pub fn drag(
+        &mut self, mvp: &Mat4<f32>, size: Vec2<f32>, screen: Vec2<f32>,
+        dem: Option<&Dem>) {
+
+        if let Some(i) = self.dragging {
+            if let Some(g) = screen_to_ground(mvp, size, screen, dem) {
+                self.waypoints[i] = g.xy();
+            }
+        }
+    }
+
+    // Stop dragging
+    pub fn release(&mut self) {
+        self.dragging = None;
+    }
+
+    // Delete the waypoint under a screen point, if there is one
+    pub fn delete(
+        &mut self, mvp: &Mat4<f32>, size: Vec2<f32>, screen: Vec2<f32>,
+        dem: Option<&Dem>) {
+
+        if let Some(i) = self.pick(mvp, size, screen, dem) {
+            self.waypoints.remove(i);
+            self.dragging = None;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.waypoints.clear();
+        self.dragging = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::virtspace::camera::Camera;
+
+    const VIEW_SIZE: Vec2<f32> = Vec2 { x: 800.0, y: 600.0 };
+
+    fn mvp() -> Mat4<f32> {
+        Camera::default().mvp(VIEW_SIZE.x, VIEW_SIZE.y, None)
+    }
+
+    // Where a point on flat ground is drawn
+    fn screen(x: f32, y: f32) -> Vec2<f32> {
+        world_to_screen(&mvp(), VIEW_SIZE, Vec3::new(x, y, 0.0)).unwrap()
+    }
+
+    fn assert_waypoints(plan: &PathPlan, expected: &[(f32, f32)]) {
+        assert_eq!(plan.waypoints().len(), expected.len());
+        for (w, (x, y)) in plan.waypoints().iter().zip(expected.iter()) {
+            assert!(
+                w.distance(Vec2::new(*x, *y)) < 1e-2,
+                "{:?} is not at ({}, {})", w, x, y);
+        }
+    }
+
+    #[test]
+    fn test_add_and_drag_waypoints() {
+        let mvp = mvp();
+        let mut plan = PathPlan::default();
+
+        // Clicking the ground adds waypoints
+        plan.press(&mvp, VIEW_SIZE, screen(12.0, 5.0), None);
+        plan.release();
+        plan.press(&mvp, VIEW_SIZE, screen(16.0, 9.0), None);
+        plan.release();
+        assert_waypoints(&plan, &[(12.0, 5.0), (16.0, 9.0)]);
+
+        // Clicking close to a waypoint picks it up rather than adding one
+        let near = screen(12.0, 5.0) + Vec2::new(6.0, 0.0);
+        plan.press(&mvp, VIEW_SIZE, near, None);
+        plan.drag(&mvp, VIEW_SIZE, screen(13.0, 4.0), None);
+        plan.release();
+        assert_waypoints(&plan, &[(13.0, 4.0), (16.0, 9.0)]);
+
+        // Once released it stays put
+        plan.drag(&mvp, VIEW_SIZE, screen(10.0, 10.0), None);
+        assert_waypoints(&plan, &[(13.0, 4.0), (16.0, 9.0)]);
+
+        // Clicking just outside the pick radius adds another
+        let far = screen(16.0, 9.0) + Vec2::new(0.0, 12.0);
+        plan.press(&mvp, VIEW_SIZE, far, None);
+        plan.release();
+        assert_eq!(plan.waypoints().len(), 3);
+    }
+
+    #[test]
+    fn test_delete_waypoints() {
+        let mvp = mvp();
+        let mut plan = PathPlan::default();
+
+        for (x, y) in [(12.0, 5.0), (14.0, 7.0), (16.0, 9.0)].iter() {
+            plan.press(&mvp, VIEW_SIZE, screen(*x, *y), None);
+            plan.release();
+        }
+
+        // Nothing is deleted away from the waypoints
+        plan.delete(&mvp, VIEW_SIZE, screen(20.0, 3.0), None);
+        assert_eq!(plan.waypoints().len(), 3);
+
+        let near = screen(14.0, 7.0) + Vec2::new(-5.0, 5.0);
+        plan.delete(&mvp, VIEW_SIZE, near, None);
+        assert_waypoints(&plan, &[(12.0, 5.0), (16.0, 9.0)]);
+
+        plan.clear();
+        assert!(plan.waypoints().is_empty());
+    }
+}
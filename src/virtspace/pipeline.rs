@@ -1,13 +1,300 @@
 use orbtk::prelude::*;
 use euc::{buffer::Buffer2d, rasterizer, Pipeline};
-use std::cell::Cell;
+use chrono::{DateTime, Utc};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::time::Instant;
 use vek::*;
 
-use crate::virtspace::{rgba_to_bgra_u32, objects::*};
+use crate::virtspace::{
+    rgba_to_bgra_u32, objects::*, camera::Camera, terrain::Terrain};
+use autonomy_control::tm_deconstructor::TmRoverPose;
+
+// ---------------------------------------------------------------------------
+// CONSTANTS
+// ---------------------------------------------------------------------------
+
+// Longest time taken to move the rover model between two pose samples. If
+// samples are further apart than this the model catches up sooner rather
+// than lagging a long way behind the rover.
+const MAX_POSE_INTERP_PERIOD_S: f32 = 1.0;
+
+// Default length of the rover's trail, as the most points kept and the age
+// at which points have faded out
+const DEFAULT_TRAIL_MAX_POINTS: usize = 2000;
+const DEFAULT_TRAIL_DECAY_S: f32 = 120.0;
+
+// A new trail point is only added once the rover has moved this far from the
+// last one, so the trail doesn't fill up while the rover is stood still
+const TRAIL_MIN_SPACING_M: f32 = 0.05;
+
+// ---------------------------------------------------------------------------
+// ROVER POSE
+// ---------------------------------------------------------------------------
+
+// The rover's pose in the virtual space
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RoverPose {
+    pub position: Vec3<f32>,
+    pub attitude: Quaternion<f32>
+}
+
+impl From<&TmRoverPose> for RoverPose {
+    fn from(tm: &TmRoverPose) -> Self {
+        let [x, y, z] = tm.position_m;
+        let [qx, qy, qz, qw] = tm.attitude_q;
+
+        RoverPose {
+            position: Vec3::new(x as f32, y as f32, z as f32),
+            attitude: Quaternion::from_xyzw(
+                qx as f32, qy as f32, qz as f32, qw as f32).normalized()
+        }
+    }
+}
+
+impl RoverPose {
+
+    // Get the rover's heading, the angle of its x axis about z from the
+    // world x axis
+    pub fn heading_rad(&self) -> f32 {
+        let q = self.attitude;
+        (2.0 * (q.w * q.z + q.x * q.y))
+            .atan2(1.0 - 2.0 * (q.y * q.y + q.z * q.z))
+    }
+}
+
+// Smooths the rover model's motion between pose TMs, which arrive far less
+// often than the view is drawn. When a new sample arrives the model moves
+// from wherever it's currently drawn to the new pose, taking as long as the
+// rover took between the last two samples. The model is therefore drawn 
+// about one sample behind the rover.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PoseInterpolator {
+    // The pose being moved from and the latest pose received
+    from: RoverPose,
+    to: RoverPose,
+
+    // Rover time of the latest pose
+    to_timestamp: Option<DateTime<Utc>>,
+
+    // Local time at which the latest pose was received
+    to_received: Option<Instant>,
+
+    // Time to take moving from `from` to `to`
+    period_s: f32
+}
+
+impl PoseInterpolator {
+
+    // Add a new pose sample, received now. Samples older than the latest one
+    // are ignored.
+    pub fn push(&mut self, pose: RoverPose, timestamp: DateTime<Utc>) {
+        let now = Instant::now();
+
+        match self.to_timestamp {
+            Some(t) if timestamp <= t => return,
+            Some(t) => {
+                let dt_s = (timestamp - t).num_milliseconds() as f32 / 1000.0;
+                self.from = self.pose_at(now);
+                self.period_s = dt_s.min(MAX_POSE_INTERP_PERIOD_S);
+            },
+            None => {
+                self.from = pose;
+                self.period_s = 0.0;
+            }
+        }
+
+        self.to = pose;
+        self.to_timestamp = Some(timestamp);
+        self.to_received = Some(now);
+    }
+
+    // Get the pose to draw at the local time `now`. Until a pose has been
+    // received this is just the default pose.
+    pub fn pose_at(&self, now: Instant) -> RoverPose {
+        let received = match self.to_received {
+            Some(r) => r,
+            None => return self.to
+        };
+
+        if self.period_s <= 0.0 {
+            return self.to
+        }
+
+        let elapsed_s = now.saturating_duration_since(received).as_secs_f32();
+        let factor = (elapsed_s / self.period_s).min(1.0);
+
+        RoverPose {
+            position: Vec3::lerp(self.from.position, self.to.position, factor),
+            attitude: Quaternion::slerp(
+                self.from.attitude, self.to.attitude, factor)
+        }
+    }
+
+    // Returns true once a pose has been received
+    pub fn has_pose(&self) -> bool {
+        self.to_received.is_some()
+    }
+
+    // Get the pose to draw at the local time `now`, or None if no pose has
+    // been received yet
+    pub fn current_pose(&self, now: Instant) -> Option<RoverPose> {
+        if self.has_pose() {
+            Some(self.pose_at(now))
+        }
+        else {
+            None
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// ROVER TRAIL
+// ---------------------------------------------------------------------------
+
+// What the colour of the rover's trail shows
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrailColour {
+    // How fast the rover was going, from blue when stopped to red at full
+    // speed
+    Speed,
+
+    // When the rover was there, from blue for the oldest point to yellow for
+    // the newest
+    Time
+}
+
+// How much of the rover's trail is kept and how it's drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrailConfig {
+    // Most points kept, the oldest are dropped first
+    pub max_points: usize,
+
+    // Age in seconds at which points have faded out and are dropped, or None
+    // to keep them until there are too many
+    pub decay_s: Option<f32>,
+
+    pub colour: TrailColour
+}
+
+impl Default for TrailConfig {
+    fn default() -> Self {
+        TrailConfig {
+            max_points: DEFAULT_TRAIL_MAX_POINTS,
+            decay_s: Some(DEFAULT_TRAIL_DECAY_S),
+            colour: TrailColour::Speed
+        }
+    }
+}
+
+// A point on the rover's trail
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrailPoint {
+    pub position: Vec3<f32>,
+
+    // The rover's speed on reaching the point
+    pub speed_mps: f32,
+
+    // Local time at which the point was received
+    pub received: Instant
+}
+
+// The history of the rover's position, drawn as a trail on the ground
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PoseTrail {
+    pub config: TrailConfig,
+    points: VecDeque<TrailPoint>,
+
+    // Position and rover time of the latest pose, used to find the speed
+    last_sample: Option<(Vec3<f32>, DateTime<Utc>)>
+}
+
+impl PoseTrail {
+
+    // Add a new pose sample, received now. Samples older than the latest one
+    // are ignored.
+    pub fn push(&mut self, pose: &RoverPose, timestamp: DateTime<Utc>) {
+        let speed_mps = match self.last_sample {
+            Some((_, t)) if timestamp <= t => return,
+            Some((p, t)) => {
+                let dt_s = (timestamp - t).num_milliseconds().max(1) as f32
+                    / 1000.0;
+                pose.position.distance(p) / dt_s
+            },
+            None => 0.0
+        };
+
+        self.last_sample = Some((pose.position, timestamp));
+
+        if let Some(last) = self.points.back() {
+            if pose.position.distance(last.position) < TRAIL_MIN_SPACING_M {
+                return
+            }
+        }
+
+        self.points.push_back(TrailPoint {
+            position: pose.position,
+            speed_mps,
+            received: Instant::now()
+        });
+    }
+
+    // Drop the points which have faded out by the local time `now`, and the 
+    // oldest points if there are too many
+    pub fn prune(&mut self, now: Instant) {
+        while self.points.len() > self.config.max_points {
+            self.points.pop_front();
+        }
+
+        if let Some(decay_s) = self.config.decay_s {
+            while let Some(p) = self.points.front() {
+                if now.saturating_duration_since(p.received).as_secs_f32() 
+                    < decay_s {
+                    break;
+                }
+                self.points.pop_front();
+            }
+        }
+    }
+
+    // Remove the whole trail. The rover's speed is still found from its
+    // last pose.
+    pub fn clear(&mut self) {
+        self.points.clear();
+    }
+
+    // Get the points of the trail, oldest first
+    pub fn points(&self) -> &VecDeque<TrailPoint> {
+        &self.points
+    }
+}
+
+// ---------------------------------------------------------------------------
+// VIRTSPACE PIPELINE
+// ---------------------------------------------------------------------------
 
 #[derive(Clone, Default, PartialEq, Pipeline)]
 pub struct VirtSpacePipeline {
-    pub frame_counter: Cell<u64>
+    pub frame_counter: Cell<u64>,
+
+    // The rover's pose from TM, the rover isn't drawn until one's received
+    pub rover_pose: Cell<PoseInterpolator>,
+
+    // The camera the view is drawn from, moved by the operator
+    pub camera: Cell<Camera>,
+
+    // Where the rover has been, drawn behind it
+    pub rover_trail: RefCell<PoseTrail>,
+
+    // Waypoints of the path the operator is planning
+    pub planned_path: RefCell<Vec<Vec2<f32>>>,
+
+    // The ground the rover is on, drawn under it, or None if it's flat
+    pub terrain: RefCell<Option<Terrain>>,
+
+    // Size of the view when it was last drawn, used to find what the 
+    // operator clicks on
+    pub view_size: Cell<Vec2<f32>>
 }
 
 impl render::RenderPipeline for VirtSpacePipeline {
@@ -27,12 +314,30 @@ impl render::RenderPipeline for VirtSpacePipeline {
             1.0,
         );
 
-        let mvp = Mat4::perspective_fov_rh_no(
-                1.3, 
-                render_target.width() as f32, render_target.height() as f32, 
-                0.01, 100.0)
-            * Mat4::translation_3d(Vec3::new(-14.0, -3.0, -10.0))
-            * Mat4::rotation_x(-0.78);
+        let now = Instant::now();
+        let rover_pose = self.rover_pose.get().current_pose(now);
+
+        self.view_size.set(Vec2::new(
+            render_target.width() as f32, render_target.height() as f32));
+
+        let mvp = self.camera.get().mvp(
+            render_target.width() as f32, render_target.height() as f32,
+            rover_pose);
+
+        // Terrain
+
+        let terrain = self.terrain.borrow();
+        let dem = terrain.as_ref().map(|t| &t.dem);
+
+        if let Some(t) = terrain.as_ref() {
+            ColouredVertices {
+                mvp: &mvp,
+                positions: &t.positions,
+                colours: &t.colours
+            }
+            .draw::<rasterizer::Triangles<_>, _>(
+                t.indices.as_slice(), &mut color, &mut depth);
+        }
 
         // World Grid
 
@@ -46,23 +351,181 @@ impl render::RenderPipeline for VirtSpacePipeline {
         .draw::<rasterizer::Lines<_>,_>(
             order.as_slice(), &mut color, &mut depth);
 
-        // Rover Body
+        // Rover Trail
 
-        let x_pos = (self.frame_counter.get() as f32 * 0.01).sin() * 5.0 + 5.0;
-        let y_pos = (self.frame_counter.get() as f32 * 0.01).cos() * 5.0 + 5.0;
+        let (trail_pos, trail_colours, order) = RoverTrail::build(
+            &self.rover_trail.borrow(), now, dem);
 
-        let rov_body = RoverBody::new(
-            &mvp,
-            Vec3::new(1.0, 1.0, 1.0), 
-            Vec3::new(x_pos, y_pos, 0.0), 
-            Mat4::rotation_z((self.frame_counter.get() as f32 * 0.01).sin() * 3.14));
+        ColouredVertices {
+            mvp: &mvp,
+            positions: &trail_pos,
+            colours: &trail_colours
+        }
+        .draw::<rasterizer::Lines<_>,_>(
+            order.as_slice(), &mut color, &mut depth);
 
-        rov_body.draw::<rasterizer::Triangles<_, rasterizer::BackfaceCullingEnabled>, _>(
-            &RoverBody::face_colours(),
-            &mut color,
-            &mut depth
-        );
+        // Planned Path
+
+        let (path_pos, path_colours, order) = PlannedPath::build(
+            &self.planned_path.borrow(), rover_pose.map(|p| p.position), dem);
+
+        ColouredVertices {
+            mvp: &mvp,
+            positions: &path_pos,
+            colours: &path_colours
+        }
+        .draw::<rasterizer::Lines<_>,_>(
+            order.as_slice(), &mut color, &mut depth);
+
+        // Rover Body
+
+        if let Some(pose) = rover_pose {
+            let rov_body = RoverBody::new(
+                &mvp,
+                Vec3::new(1.0, 1.0, 1.0), 
+                pose.position, 
+                Mat4::from(pose.attitude));
+
+            rov_body.draw::<rasterizer::Triangles<_, rasterizer::BackfaceCullingEnabled>, _>(
+                &RoverBody::face_colours(),
+                &mut color,
+                &mut depth
+            );
+        }
 
         render_target.draw(color.as_ref());
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    fn pose(x: f32, heading_rad: f32) -> RoverPose {
+        RoverPose {
+            position: Vec3::new(x, 0.0, 0.0),
+            attitude: Quaternion::rotation_z(heading_rad)
+        }
+    }
+
+    // Rover time `ms` milliseconds after an arbitrary start
+    fn rover_time(ms: u64) -> DateTime<Utc> {
+        DateTime::from(UNIX_EPOCH + Duration::from_millis(ms))
+    }
+
+    fn assert_pose(actual: RoverPose, x: f32, heading_rad: f32) {
+        assert!(
+            (actual.position.x - x).abs() < 1e-4
+                && (actual.heading_rad() - heading_rad).abs() < 1e-4,
+            "{:?} is not at x = {} heading {}", actual, x, heading_rad);
+    }
+
+    #[test]
+    fn test_pose_interpolation() {
+        let mut interp = PoseInterpolator::default();
+        assert!(!interp.has_pose());
+        assert_eq!(interp.current_pose(Instant::now()), None);
+
+        // The first pose is drawn straight away
+        interp.push(pose(0.0, 0.0), rover_time(0));
+        assert_pose(interp.current_pose(Instant::now()).unwrap(), 0.0, 0.0);
+
+        // The next is moved to over the time between the samples
+        interp.push(pose(2.0, 1.0), rover_time(500));
+        let received = interp.to_received.unwrap();
+        let at = |ms: u64| interp.pose_at(received + Duration::from_millis(ms));
+
+        assert_pose(at(0), 0.0, 0.0);
+        assert_pose(at(250), 1.0, 0.5);
+        assert_pose(at(500), 2.0, 1.0);
+        assert_pose(at(5000), 2.0, 1.0);
+
+        // Older and repeated samples are ignored
+        interp.push(pose(5.0, 0.0), rover_time(400));
+        interp.push(pose(5.0, 0.0), rover_time(500));
+        assert_eq!(interp.to_received, Some(received));
+        assert_pose(
+            interp.pose_at(received + Duration::from_secs(1)), 2.0, 1.0);
+    }
+
+    #[test]
+    fn test_pose_interpolation_period_limit() {
+        let mut interp = PoseInterpolator::default();
+        interp.push(pose(0.0, 0.0), rover_time(0));
+
+        // Samples far apart are caught up with in at most the longest period
+        interp.push(pose(4.0, 0.0), rover_time(10_000));
+        assert_eq!(interp.period_s, MAX_POSE_INTERP_PERIOD_S);
+
+        let received = interp.to_received.unwrap();
+        let at = |s: f32| interp.pose_at(received + Duration::from_secs_f32(s));
+
+        assert_pose(at(0.5 * MAX_POSE_INTERP_PERIOD_S), 2.0, 0.0);
+        assert_pose(at(MAX_POSE_INTERP_PERIOD_S), 4.0, 0.0);
+    }
+
+    #[test]
+    fn test_trail_spacing_and_speed() {
+        let mut trail = PoseTrail::default();
+
+        trail.push(&pose(0.0, 0.0), rover_time(0));
+        trail.push(&pose(1.0, 0.0), rover_time(500));
+
+        // Too close to the last point to add another
+        trail.push(&pose(1.04, 0.0), rover_time(1000));
+
+        // Older than the latest sample
+        trail.push(&pose(3.0, 0.0), rover_time(900));
+
+        // The speed is found from the last sample, not the last point
+        trail.push(&pose(1.54, 0.0), rover_time(1500));
+
+        let xs: Vec<f32> = trail.points().iter()
+            .map(|p| p.position.x)
+            .collect();
+        let speeds: Vec<f32> = trail.points().iter()
+            .map(|p| p.speed_mps)
+            .collect();
+
+        assert_eq!(xs, vec![0.0, 1.0, 1.54]);
+        assert_eq!(speeds, vec![0.0, 2.0, 1.0]);
+
+        // Clearing the trail keeps the last sample for the speed
+        trail.clear();
+        assert!(trail.points().is_empty());
+        trail.push(&pose(2.04, 0.0), rover_time(2000));
+        assert_eq!(trail.points()[0].speed_mps, 1.0);
+    }
+
+    #[test]
+    fn test_trail_pruning() {
+        let mut trail = PoseTrail::default();
+        trail.config.max_points = 3;
+        trail.config.decay_s = Some(10.0);
+
+        for i in 0..5 {
+            trail.push(&pose(i as f32, 0.0), rover_time(i * 1000));
+        }
+        assert_eq!(trail.points().len(), 5);
+
+        // Only the newest points are kept
+        let now = Instant::now();
+        trail.prune(now);
+        let xs: Vec<f32> = trail.points().iter()
+            .map(|p| p.position.x)
+            .collect();
+        assert_eq!(xs, vec![2.0, 3.0, 4.0]);
+
+        // Until they fade out
+        trail.prune(now + Duration::from_secs(5));
+        assert_eq!(trail.points().len(), 3);
+        trail.prune(now + Duration::from_secs(11));
+        assert!(trail.points().is_empty());
+
+        // Or are kept until there are too many when they don't fade
+        trail.config.decay_s = None;
+        trail.push(&pose(5.0, 0.0), rover_time(5000));
+        trail.prune(now + Duration::from_secs(1000));
+        assert_eq!(trail.points().len(), 1);
+    }
+}
@@ -59,4 +59,28 @@ impl Pipeline for RoverBody {
             | (bytes[0] as u32) << 16
             | (bytes[3] as u32) << 24
     }
-}
\ No newline at end of file
+}
+
+// ---------------------------------------------------------------------------
+// COLOURED VERTICES
+// ---------------------------------------------------------------------------
+
+impl<'a> Pipeline for ColouredVertices<'a> {
+    type Vertex = u32;
+    type VsOut = Rgba<f32>;
+    type Pixel = u32;
+
+    // Colours are blended between the points of each line or triangle
+    #[inline(always)]
+    fn vert(&self, index: &Self::Vertex) -> ([f32; 4], Self::VsOut) {
+        let i = *index as usize;
+
+        ((*self.mvp * self.positions[i]).into_array(), self.colours[i])
+    }
+
+    #[inline(always)]
+    fn frag(&self, colour: &Self::VsOut) -> Self::Pixel {
+        let bytes = colour.map(|e| (e * 255.0) as u8).into_array();
+        rgba_to_bgra_u32(bytes[0], bytes[1], bytes[2], bytes[3])
+    }
+}
@@ -0,0 +1,313 @@
+// ---------------------------------------------------------------------------
+// TERRAIN
+//
+// A digital elevation model (DEM) of the site the rover is on, drawn as the
+// ground in the virtspace. A DEM file is JSON, giving the position of the
+// first height, the spacing between heights, and the heights themselves as
+// rows running along x, the first row being the one with the lowest y:
+//
+//     {
+//         "origin_m": [-5.0, -5.0],
+//         "resolution_m": 0.5,
+//         "heights_m": [
+//             [0.0, 0.1, 0.2],
+//             [0.1, 0.2, 0.3]
+//         ]
+//     }
+//
+// Every row must have the same number of heights. Outside the DEM the ground
+// is taken to be flat at z = 0.
+// ---------------------------------------------------------------------------
+
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use vek::*;
+
+use crate::virtspace::objects::TerrainMesh;
+
+// ---------------------------------------------------------------------------
+// DEM FILE
+// ---------------------------------------------------------------------------
+
+// A DEM as it's written in the file, before it's been checked
+#[derive(Deserialize)]
+struct DemFile {
+    origin_m: [f32; 2],
+    resolution_m: f32,
+    heights_m: Vec<Vec<f32>>
+}
+
+// ---------------------------------------------------------------------------
+// DEM
+// ---------------------------------------------------------------------------
+
+// A grid of ground heights
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dem {
+    // Position of the first height
+    origin_m: Vec2<f32>,
+
+    // Spacing between heights along both x and y
+    resolution_m: f32,
+
+    // Number of heights along x and y
+    num_x: usize,
+    num_y: usize,
+
+    // Heights by row, each row running along x
+    heights_m: Vec<f32>
+}
+
+impl Dem {
+
+    // Load a DEM from a JSON file
+    pub fn from_file<P>(path: P) -> Result<Self, String> where P: AsRef<Path> {
+        let json_str = fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read {:?}: {}", path.as_ref(), e))?;
+
+        Dem::from_json(&json_str)
+    }
+
+    // Parse a DEM from a JSON string, checking it's a complete grid
+    pub fn from_json(json_str: &str) -> Result<Self, String> {
+        let file: DemFile = serde_json::from_str(json_str)
+            .map_err(|e| format!("Invalid DEM: {}", e))?;
+
+        if !file.resolution_m.is_finite() || file.resolution_m <= 0.0 {
+            return Err(format!(
+                "resolution_m must be more than 0 but is {}",
+                file.resolution_m))
+        }
+
+        let num_y = file.heights_m.len();
+        let num_x = file.heights_m.first().map(|r| r.len()).unwrap_or(0);
+
+        check_grid_size(num_x, num_y)?;
+
+        let mut heights_m = Vec::with_capacity(num_x * num_y);
+
+        for (i, row) in file.heights_m.into_iter().enumerate() {
+            if row.len() != num_x {
+                return Err(format!(
+                    "Row {} has {} heights but the first row has {}",
+                    i, row.len(), num_x))
+            }
+
+            if let Some(h) = row.iter().find(|h| !h.is_finite()) {
+                return Err(format!("Row {} has an invalid height {}", i, h))
+            }
+
+            heights_m.extend(row);
+        }
+
+        Ok(Dem {
+            origin_m: Vec2::from(file.origin_m),
+            resolution_m: file.resolution_m,
+            num_x,
+            num_y,
+            heights_m
+        })
+    }
+
+    pub fn num_x(&self) -> usize {
+        self.num_x
+    }
+
+    pub fn num_y(&self) -> usize {
+        self.num_y
+    }
+
+    // Get the lowest and highest heights
+    pub fn height_range(&self) -> (f32, f32) {
+        self.heights_m.iter().fold(
+            (f32::INFINITY, f32::NEG_INFINITY),
+            |(l, h), z| (l.min(*z), h.max(*z)))
+    }
+
+    // Get the position of the height at column `i` of row `j`
+    pub fn point(&self, i: usize, j: usize) -> Vec3<f32> {
+        Vec3::new(
+            self.origin_m.x + i as f32 * self.resolution_m,
+            self.origin_m.y + j as f32 * self.resolution_m,
+            self.heights_m[j * self.num_x + i])
+    }
+
+    // Get the ground's normal at column `i` of row `j`, from the slope to the
+    // heights either side of it
+    pub fn normal(&self, i: usize, j: usize) -> Vec3<f32> {
+        let height = |i: usize, j: usize| self.heights_m[j * self.num_x + i];
+
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.num_x - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.num_y - 1));
+
+        let dz_dx = (height(i1, j) - height(i0, j))
+            / ((i1 - i0) as f32 * self.resolution_m);
+        let dz_dy = (height(i, j1) - height(i, j0))
+            / ((j1 - j0) as f32 * self.resolution_m);
+
+        Vec3::new(-dz_dx, -dz_dy, 1.0).normalized()
+    }
+
+    // Get the height of the ground at a point, interpolated between the
+    // heights around it, or None if the point is outside the DEM
+    pub fn height_at(&self, point: Vec2<f32>) -> Option<f32> {
+        let grid = (point - self.origin_m) / self.resolution_m;
+
+        if grid.x < 0.0 || grid.y < 0.0
+            || grid.x > (self.num_x - 1) as f32
+            || grid.y > (self.num_y - 1) as f32 {
+            return None
+        }
+
+        let i = (grid.x.floor() as usize).min(self.num_x - 2);
+        let j = (grid.y.floor() as usize).min(self.num_y - 2);
+        let (fx, fy) = (grid.x - i as f32, grid.y - j as f32);
+
+        let height = |i: usize, j: usize| self.heights_m[j * self.num_x + i];
+        let lower = height(i, j) * (1.0 - fx) + height(i + 1, j) * fx;
+        let upper = height(i, j + 1) * (1.0 - fx) + height(i + 1, j + 1) * fx;
+
+        Some(lower * (1.0 - fy) + upper * fy)
+    }
+}
+
+// Check a DEM has enough heights to make a grid, but not so many that they
+// can't be drawn, as the mesh indexes them with `u32`s
+fn check_grid_size(num_x: usize, num_y: usize) -> Result<(), String> {
+    if num_x < 2 || num_y < 2 {
+        return Err(format!(
+            "A DEM needs at least 2 by 2 heights but has {} by {}",
+            num_x, num_y))
+    }
+
+    match num_x.checked_mul(num_y) {
+        Some(n) if n <= u32::MAX as usize => Ok(()),
+        _ => Err(format!(
+            "A DEM can have at most {} heights but has {} by {}",
+            u32::MAX, num_x, num_y))
+    }
+}
+
+// Get the height of the ground at a point, from the DEM if there is one and
+// the point is on it, otherwise 0
+pub fn ground_height(dem: Option<&Dem>, point: Vec2<f32>) -> f32 {
+    dem.and_then(|d| d.height_at(point)).unwrap_or(0.0)
+}
+
+// ---------------------------------------------------------------------------
+// TERRAIN
+// ---------------------------------------------------------------------------
+
+// A DEM along with the mesh it's drawn with, which is only built once when
+// the DEM is loaded
+#[derive(Clone, Debug, PartialEq)]
+pub struct Terrain {
+    pub dem: Dem,
+    pub positions: Vec<Vec4<f32>>,
+    pub colours: Vec<Rgba<f32>>,
+    pub indices: Vec<u32>
+}
+
+impl Terrain {
+
+    pub fn new(dem: Dem) -> Self {
+        let (positions, colours, indices) = TerrainMesh::build(&dem);
+
+        Terrain {
+            dem,
+            positions,
+            colours,
+            indices
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A DEM with the given heights, 1 m apart from the origin (10, 20)
+    fn dem_json(heights_m: &str) -> String {
+        format!(
+            r#"{{ "origin_m": [10.0, 20.0], "resolution_m": 1.0,
+                "heights_m": {} }}"#, heights_m)
+    }
+
+    // Check a DEM is refused, and that the reason mentions `what`
+    fn assert_invalid(json_str: &str, what: &str) {
+        match Dem::from_json(json_str) {
+            Err(e) => assert!(e.contains(what), "{}", e),
+            Ok(d) => panic!("Expected an invalid DEM but got {:?}", d)
+        }
+    }
+
+    #[test]
+    fn test_dem_parse() {
+        let dem = Dem::from_json(
+            &dem_json("[[0.0, 1.0, 2.0], [3.0, 4.0, 5.0]]")).unwrap();
+
+        assert_eq!((dem.num_x(), dem.num_y()), (3, 2));
+        assert_eq!(dem.point(2, 1), Vec3::new(12.0, 21.0, 5.0));
+        assert_eq!(dem.height_range(), (0.0, 5.0));
+        assert_eq!(Terrain::new(dem).positions.len(), 6);
+    }
+
+    #[test]
+    fn test_dem_bad_resolution() {
+        for resolution_m in ["0.0", "-1.0", "1e39"].iter() {
+            assert_invalid(
+                &format!(
+                    r#"{{ "origin_m": [0.0, 0.0], "resolution_m": {},
+                        "heights_m": [[0.0, 0.0], [0.0, 0.0]] }}"#,
+                    resolution_m),
+                "resolution_m");
+        }
+    }
+
+    #[test]
+    fn test_dem_bad_grid() {
+        assert_invalid(&dem_json("[]"), "at least 2 by 2");
+        assert_invalid(&dem_json("[[0.0, 0.0]]"), "at least 2 by 2");
+        assert_invalid(&dem_json("[[0.0], [0.0]]"), "at least 2 by 2");
+        assert_invalid(&dem_json("[[0.0, 0.0], [0.0]]"), "Row 1");
+        assert_invalid(
+            &dem_json("[[0.0, 0.0], [0.0, 1e39]]"), "invalid height");
+        assert_invalid("{ \"origin_m\": [0.0, 0.0] }", "Invalid DEM");
+    }
+
+    #[test]
+    fn test_dem_too_big() {
+        assert!(check_grid_size(65535, 65537).is_ok());
+        assert!(check_grid_size(65536, 65536).is_err());
+        assert!(check_grid_size(usize::MAX, 2).is_err());
+    }
+
+    #[test]
+    fn test_height_at() {
+        let dem = Dem::from_json(&dem_json("[[0.0, 1.0], [2.0, 3.0]]"))
+            .unwrap();
+        let height = |x: f32, y: f32| dem.height_at(Vec2::new(x, y));
+
+        // At the heights themselves, including the far edges
+        assert_eq!(height(10.0, 20.0), Some(0.0));
+        assert_eq!(height(11.0, 20.0), Some(1.0));
+        assert_eq!(height(10.0, 21.0), Some(2.0));
+        assert_eq!(height(11.0, 21.0), Some(3.0));
+
+        // Interpolated between them
+        assert_eq!(height(10.5, 20.5), Some(1.5));
+        assert_eq!(height(10.25, 20.75), Some(1.75));
+
+        // Off the grid
+        assert_eq!(height(9.9, 20.5), None);
+        assert_eq!(height(11.1, 20.5), None);
+        assert_eq!(height(10.5, 19.9), None);
+        assert_eq!(height(10.5, 21.1), None);
+
+        // Off the DEM the ground is flat
+        assert_eq!(ground_height(Some(&dem), Vec2::new(0.0, 0.0)), 0.0);
+        assert_eq!(ground_height(Some(&dem), Vec2::new(10.5, 20.5)), 1.5);
+        assert_eq!(ground_height(None, Vec2::new(10.5, 20.5)), 0.0);
+    }
+}
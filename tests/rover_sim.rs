@@ -0,0 +1,115 @@
+// ---------------------------------------------------------------------------
+// ROVER SIMULATOR INTEGRATION TESTS
+//
+// Start the `rover_sim` binary and talk to it over the real TM/TC interface,
+// checking TCs make the round trip to the rover and back as acknowledgements.
+// ---------------------------------------------------------------------------
+
+use std::net::TcpListener;
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use autonomy_control::tm_tc_interface::{
+    TmTcIf, TmTcIfConfig, LinkState, TcStatus};
+use autonomy_control::tm_tc_types::RoverMode;
+use autonomy_control::tc_constructor::{
+    TcConstructor, TcSetMode, TcDriveStraight, TcDisconnect};
+
+// How long to wait for the simulator to do anything
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+// The simulator process, killed when the test finishes even if it fails
+struct RoverSim(Child);
+
+impl RoverSim {
+
+    // Start the simulator listening on a free port, returning it and the port
+    fn start() -> (Self, u16) {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .and_then(|l| l.local_addr())
+            .expect("Cannot find a free port")
+            .port();
+
+        let child = Command::new(env!("CARGO_BIN_EXE_rover_sim"))
+            .args(["--port", &port.to_string()])
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Cannot start rover_sim");
+
+        (RoverSim(child), port)
+    }
+}
+
+impl Drop for RoverSim {
+    fn drop(&mut self) {
+        self.0.kill().ok();
+        self.0.wait().ok();
+    }
+}
+
+// Start an interface connected to the simulator on `port`, waiting until the
+// link is up
+fn connect(port: u16) -> TmTcIf {
+    let config = TmTcIfConfig {
+        rover_port: port,
+        reconnect_delay_min: Duration::from_millis(50),
+        ..TmTcIfConfig::default()
+    };
+
+    let tm_tc_if = TmTcIf::start(config);
+    let deadline = Instant::now() + TIMEOUT;
+
+    while tm_tc_if.link_state() != LinkState::Connected {
+        assert!(Instant::now() < deadline, "Link to rover_sim never came up");
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    tm_tc_if
+}
+
+#[test]
+fn test_tc_acknowledged_by_rover() {
+    let (_sim, port) = RoverSim::start();
+    let mut tc_cstr = TcConstructor::new(connect(port));
+
+    let handle = tc_cstr.build_and_send(TcSetMode::new(RoverMode::Standby))
+        .unwrap();
+
+    assert_eq!(handle.wait(TIMEOUT).unwrap(), TcStatus::Completed);
+    assert!(handle.frame_counter().is_some());
+
+    let report = tc_cstr.stop().unwrap();
+    assert!(report.disconnect_acknowledged);
+    assert!(report.undelivered.is_empty());
+}
+
+#[test]
+fn test_tc_rejected_by_rover() {
+    let (_sim, port) = RoverSim::start();
+    let mut tm_tc_if = connect(port);
+
+    // The simulator starts in Standby, where it won't drive
+    let handle = tm_tc_if
+        .add_pending_tc(TcDriveStraight::new(1.0, 0.2).unwrap())
+        .unwrap();
+
+    match handle.wait_for_ack(TIMEOUT).unwrap() {
+        TcStatus::Rejected(_) => (),
+        s => panic!("Expected the TC to be rejected but it's {}", s)
+    }
+
+    tm_tc_if.stop().unwrap();
+}
+
+#[test]
+fn test_disconnect_completed_by_rover() {
+    let (_sim, port) = RoverSim::start();
+    let mut tm_tc_if = connect(port);
+
+    let handle = tm_tc_if.add_pending_tc(TcDisconnect::new()).unwrap();
+
+    assert_eq!(handle.wait(TIMEOUT).unwrap(), TcStatus::Completed);
+
+    tm_tc_if.stop().unwrap();
+}